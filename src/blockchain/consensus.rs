@@ -0,0 +1,74 @@
+use crate::blockchain::header::BlockHeader;
+use crate::blockchain::Block;
+use crate::wallet::{ verify_signature, Wallet };
+use std::any::Any;
+use std::fmt::Debug;
+
+/*
+    everything the chain needs to know about how blocks are produced and which
+    chain wins. the engine always gets the blocks the header builds on, genesis
+    first, so it can look back as far as it needs without owning any state of
+    the chain
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForkChoice {
+    // stay on the current chain
+    Keep,
+    // reorganize onto the candidate chain
+    Switch,
+}
+
+pub trait ConsensusEngine: Debug + Send {
+    fn name(&self) -> &'static str;
+
+    // lets callers reach engine specific methods, e.g. submitting authority votes
+    fn as_any(&self) -> &dyn Any;
+
+    // the difficulty the next block on top of ancestors has to carry
+    fn next_difficulty(&self, ancestors: &[Block]) -> u32;
+
+    // who may produce the next block, None when anyone may
+    fn next_validator(&self, ancestors: &[Block]) -> Option<String> {
+        let _ = ancestors;
+        None
+    }
+
+    /*
+        the difficulty the next block's proof of work hash has to meet, in hex
+        zeroes under the network's PowAlgorithm, None for engines that seal
+        without work. miners outside the engine, the pool and the hashrate
+        estimate only work with engines that return Some
+    */
+    fn pow_target(&self, ancestors: &[Block]) -> Option<u32> {
+        let _ = ancestors;
+        None
+    }
+
+    // public keys and stake of the validators bonded at genesis, before any
+    // stake transaction on the chain
+    fn genesis_stake(&self) -> Vec<(String, u64)> {
+        Vec::new()
+    }
+
+    // fills in whatever the engine needs in the header of a block that is
+    // otherwise complete, returns how many headers it tried, None when this
+    // node cannot seal it
+    fn seal(&self, ancestors: &[Block], block: &mut Block) -> Option<u64>;
+
+    // checks the engine specific parts of a header built on ancestors
+    fn verify_seal(&self, ancestors: &[Block], header: &BlockHeader) -> bool;
+
+    // both chains start at the same genesis block
+    fn select_fork(&self, current: &[Block], candidate: &[Block]) -> ForkChoice;
+}
+
+pub fn sign_header(signer: &Wallet, header: &mut BlockHeader) {
+    // engines that seal with a key put the signature of the signing hash in the seal
+    let signature = signer.sign_message(header.signing_hash().as_bytes());
+    header.seal = hex::decode(signature).unwrap_or_default();
+}
+
+pub fn verify_header_signature(public_key: &str, header: &BlockHeader) -> bool {
+    verify_signature(public_key, &hex::encode(&header.seal), header.signing_hash().as_bytes())
+}
@@ -0,0 +1,183 @@
+use crate::blockchain::hash::{ BlockHash, TxId };
+use crate::blockchain::transaction::Transaction;
+use crate::blockchain::{ Block, ChainTip, Serialization };
+use std::collections::HashSet;
+use std::sync::mpsc::{ channel, Receiver, Sender };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    // pushed out of a full mempool by a better paying transaction
+    Evicted,
+    // waited longer than the mempool expiry
+    Expired,
+    // replaced by fee, or spent from a replaced transaction
+    Replaced,
+    // no longer valid against the chain, e.g. a balance that went away
+    Invalid,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockEvent {
+    pub height: u64,
+    pub hash: BlockHash,
+    pub transactions: Vec<Transaction>,
+}
+
+impl BlockEvent {
+    pub fn new(block: &Block) -> Self {
+        BlockEvent {
+            height: block.height(),
+            hash: block.hash(),
+            transactions: block.transactions
+                .iter()
+                .map(|tx| Transaction::deserialization(tx.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    BlockAppended(BlockEvent),
+    // the tip block was taken off the chain, its transactions go back to the pool
+    BlockReverted(BlockEvent),
+    TransactionAccepted {
+        id: TxId,
+        transaction: Transaction,
+    },
+    TransactionDropped {
+        id: TxId,
+        transaction: Transaction,
+        reason: DropReason,
+    },
+    // the block and everything below it can no longer be reverted
+    CheckpointFinalized(ChainTip),
+    // sent now and then to a subscriber whose filter has left out every recent
+    // event, so one that went away is noticed whatever it listens for
+    Heartbeat,
+}
+
+impl ChainEvent {
+    pub fn involves(&self, address: &[u8]) -> bool {
+        match self {
+            ChainEvent::BlockAppended(block) | ChainEvent::BlockReverted(block) =>
+                block.transactions.iter().any(|transaction| transaction.involves(address)),
+            | ChainEvent::TransactionAccepted { transaction, .. }
+            | ChainEvent::TransactionDropped { transaction, .. } => transaction.involves(address),
+            ChainEvent::CheckpointFinalized(_) | ChainEvent::Heartbeat => false,
+        }
+    }
+}
+
+// which events a subscriber gets, the default gets everything
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    // only events touching one of these addresses, all events when empty
+    pub addresses: HashSet<Vec<u8>>,
+}
+
+impl EventFilter {
+    pub fn all() -> Self {
+        EventFilter::default()
+    }
+
+    pub fn addresses(addresses: &[&str]) -> Self {
+        EventFilter {
+            addresses: addresses
+                .iter()
+                .map(|address| address.as_bytes().to_vec())
+                .collect(),
+        }
+    }
+
+    pub fn matches(&self, event: &ChainEvent) -> bool {
+        self.addresses.is_empty() || self.addresses.iter().any(|address| event.involves(address))
+    }
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    filter: EventFilter,
+    sender: Sender<ChainEvent>,
+    // events left out by the filter since the last send
+    skipped: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct EventBus {
+    subscribers: Vec<Subscriber>,
+}
+
+impl EventBus {
+    // a quiet subscriber gets a heartbeat after this many left out events
+    const HEARTBEAT_EVERY: usize = 64;
+
+    pub fn new() -> Self {
+        EventBus::default()
+    }
+
+    pub fn subscribe(&mut self, filter: EventFilter) -> Receiver<ChainEvent> {
+        // dropping the receiver unsubscribes
+        let (sender, receiver) = channel::<ChainEvent>();
+        self.subscribers.push(Subscriber { filter, sender, skipped: 0 });
+        receiver
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    pub fn publish(&mut self, event: ChainEvent) {
+        // a failed send means the receiver was dropped
+        self.subscribers.retain_mut(|subscriber| {
+            let event = if subscriber.filter.matches(&event) {
+                event.clone()
+            } else {
+                subscriber.skipped += 1;
+                if subscriber.skipped < EventBus::HEARTBEAT_EVERY {
+                    return true;
+                }
+                ChainEvent::Heartbeat
+            };
+            subscriber.skipped = 0;
+            subscriber.sender.send(event).is_ok()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publish_prunes_closed_subscribers_whatever_their_filter() {
+        let mut bus = EventBus::new();
+        let filtered = bus.subscribe(EventFilter::addresses(&["nobody"]));
+        let all = bus.subscribe(EventFilter::all());
+        drop(filtered);
+
+        let tip = ChainTip { height: 1, hash: BlockHash::default() };
+        for _ in 0..EventBus::HEARTBEAT_EVERY {
+            bus.publish(ChainEvent::CheckpointFinalized(tip));
+        }
+        assert_eq!(bus.subscriber_count(), 1);
+        assert!(matches!(all.try_recv(), Ok(ChainEvent::CheckpointFinalized(_))));
+    }
+
+    #[test]
+    fn filtered_subscribers_stay_quiet() {
+        let mut bus = EventBus::new();
+        let filtered = bus.subscribe(EventFilter::addresses(&["nobody"]));
+
+        let tip = ChainTip { height: 1, hash: BlockHash::default() };
+        for _ in 1..EventBus::HEARTBEAT_EVERY {
+            bus.publish(ChainEvent::CheckpointFinalized(tip));
+        }
+        assert!(filtered.try_recv().is_err());
+
+        // one heartbeat for a whole run of left out events
+        bus.publish(ChainEvent::CheckpointFinalized(tip));
+        assert!(matches!(filtered.try_recv(), Ok(ChainEvent::Heartbeat)));
+        assert!(filtered.try_recv().is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use crate::blockchain::hash::{ tagged_hash, BlockHash, CHECKPOINT_VOTE_TAG };
+use crate::blockchain::{ Block, ChainTip };
+use crate::wallet::{ verify_signature, Wallet };
+use borsh::{ BorshDeserialize, BorshSerialize };
+use std::collections::{ BTreeMap, HashMap, HashSet };
+
+/*
+    finality on top of any consensus engine: a fixed set of validators sign
+    votes for the block hash at every checkpoint height (every `interval`
+    blocks). once more than two thirds of them vote for the same checkpoint
+    and that block is on our chain it becomes final, and the chain refuses to
+    revert it or anything below it no matter what fork choice says
+*/
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct CheckpointVote {
+    // hex public key, like Wallet::public_key_str
+    pub voter: String,
+    pub height: u64,
+    pub hash: BlockHash,
+    pub signature: String,
+}
+
+impl CheckpointVote {
+    pub fn new(wallet: &Wallet, height: u64, hash: BlockHash) -> Self {
+        let mut vote = CheckpointVote {
+            voter: wallet.public_key_str(),
+            height,
+            hash,
+            signature: String::new(),
+        };
+        vote.signature = wallet.sign_message(&vote.payload());
+        vote
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        let payload = borsh::to_vec(&(self.height, &self.hash)).expect("Borsh serialization failed");
+        tagged_hash(CHECKPOINT_VOTE_TAG, &payload).to_vec()
+    }
+
+    pub fn verify_signature(&self) -> bool {
+        verify_signature(&self.voter, &self.signature, &self.payload())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FinalityGadget {
+    validators: Vec<String>,
+    interval: u64,
+    // voters for each block hash at each checkpoint height not final yet
+    tallies: BTreeMap<u64, HashMap<BlockHash, HashSet<String>>>,
+    finalized: Option<ChainTip>,
+}
+
+impl FinalityGadget {
+    pub fn new(validators: Vec<String>, interval: u64) -> Option<Self> {
+        // every validator must be a valid public key
+        if
+            interval == 0 ||
+            validators.is_empty() ||
+            validators.iter().any(|key| Wallet::address_from_public_key(key).is_none())
+        {
+            return None;
+        }
+
+        let mut validators = validators;
+        validators.sort();
+        validators.dedup();
+        Some(FinalityGadget {
+            validators,
+            interval,
+            tallies: BTreeMap::new(),
+            finalized: None,
+        })
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    pub fn is_checkpoint(&self, height: u64) -> bool {
+        height > 0 && height.is_multiple_of(self.interval)
+    }
+
+    pub fn finalized(&self) -> Option<ChainTip> {
+        self.finalized
+    }
+
+    pub fn is_final(&self, height: u64) -> bool {
+        self.finalized.is_some_and(|checkpoint| height <= checkpoint.height)
+    }
+
+    pub fn submit_vote(&mut self, vote: &CheckpointVote) -> bool {
+        if !self.validators.contains(&vote.voter) {
+            println!("checkpoint vote is not from a validator");
+            return false;
+        }
+        if !self.is_checkpoint(vote.height) || self.is_final(vote.height) {
+            println!("height {} is not an open checkpoint", vote.height);
+            return false;
+        }
+
+        // a validator gets one vote per checkpoint
+        let votes = self.tallies.entry(vote.height).or_default();
+        if votes.values().any(|voters| voters.contains(&vote.voter)) {
+            println!("validator already voted on checkpoint {}", vote.height);
+            return false;
+        }
+        if !vote.verify_signature() {
+            println!("checkpoint vote has an invalid signature");
+            return false;
+        }
+
+        votes.entry(vote.hash).or_default().insert(vote.voter.clone());
+        true
+    }
+
+    pub fn finalize(&mut self, chain: &[Block]) -> Option<ChainTip> {
+        // the highest checkpoint on chain with a supermajority becomes final
+        let checkpoint = self.tallies
+            .iter()
+            .rev()
+            .flat_map(|(height, votes)| votes.iter().map(move |(hash, voters)| (*height, *hash, voters.len())))
+            .find(|(height, hash, voters)| {
+                voters * 3 > self.validators.len() * 2 &&
+                    chain.get(*height as usize).is_some_and(|block| block.hash() == *hash)
+            })
+            .map(|(height, hash, _)| ChainTip { height, hash })?;
+
+        self.tallies.retain(|height, _| *height > checkpoint.height);
+        self.finalized = Some(checkpoint);
+        Some(checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gadget(validators: &[Wallet]) -> FinalityGadget {
+        FinalityGadget::new(validators.iter().map(|wallet| wallet.public_key_str()).collect(), 2).unwrap()
+    }
+
+    fn blocks() -> Vec<Block> {
+        (0..5u64).map(|height| Block::new(height, 0, BlockHash::default())).collect()
+    }
+
+    #[test]
+    fn supermajority_finalizes_the_checkpoint() {
+        let validators = [Wallet::new(), Wallet::new(), Wallet::new(), Wallet::new()];
+        let mut gadget = gadget(&validators);
+        let chain = blocks();
+        let hash = chain[2].hash();
+
+        // two of four is not more than two thirds
+        assert!(gadget.submit_vote(&CheckpointVote::new(&validators[0], 2, hash)));
+        assert!(gadget.submit_vote(&CheckpointVote::new(&validators[1], 2, hash)));
+        assert_eq!(gadget.finalize(&chain), None);
+
+        assert!(gadget.submit_vote(&CheckpointVote::new(&validators[2], 2, hash)));
+        assert_eq!(gadget.finalize(&chain), Some(ChainTip { height: 2, hash }));
+        assert!(gadget.is_final(1) && gadget.is_final(2) && !gadget.is_final(3));
+
+        // a final checkpoint takes no more votes
+        assert!(!gadget.submit_vote(&CheckpointVote::new(&validators[3], 2, hash)));
+    }
+
+    #[test]
+    fn votes_for_a_block_off_the_chain_do_not_finalize() {
+        let validators = [Wallet::new()];
+        let mut gadget = gadget(&validators);
+        assert!(gadget.submit_vote(&CheckpointVote::new(&validators[0], 2, BlockHash::default())));
+        assert_eq!(gadget.finalize(&blocks()), None);
+    }
+
+    #[test]
+    fn duplicate_and_outside_votes_are_rejected() {
+        let validators = [Wallet::new(), Wallet::new()];
+        let mut gadget = gadget(&validators);
+        let chain = blocks();
+
+        assert!(gadget.submit_vote(&CheckpointVote::new(&validators[0], 2, chain[2].hash())));
+        // one vote per checkpoint, even for another block
+        assert!(!gadget.submit_vote(&CheckpointVote::new(&validators[0], 2, chain[2].hash())));
+        assert!(!gadget.submit_vote(&CheckpointVote::new(&validators[0], 2, chain[1].hash())));
+
+        assert!(!gadget.submit_vote(&CheckpointVote::new(&Wallet::new(), 2, chain[2].hash())));
+        // only checkpoint heights, and only with the voter's own signature
+        assert!(!gadget.submit_vote(&CheckpointVote::new(&validators[1], 3, chain[3].hash())));
+        let mut forged = CheckpointVote::new(&Wallet::new(), 2, chain[2].hash());
+        forged.voter = validators[1].public_key_str();
+        assert!(!gadget.submit_vote(&forged));
+    }
+}
@@ -0,0 +1,46 @@
+use crate::blockchain::hash::BlockHash;
+use crate::blockchain::header::BlockHeader;
+use crate::blockchain::pow::PowAlgorithm;
+use crate::blockchain::Block;
+use borsh::{ BorshDeserialize, BorshSerialize };
+
+/*
+    settings every node of a network has to agree on. they are encoded in the
+    extra field of the genesis header, so the genesis hash commits to them and
+    two networks with different settings never share a chain. a genesis header
+    without extra uses the defaults
+*/
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct GenesisParams {
+    pub pow_algorithm: PowAlgorithm,
+}
+
+impl GenesisParams {
+    pub fn with_pow_algorithm(mut self, algorithm: PowAlgorithm) -> Self {
+        self.pow_algorithm = algorithm;
+        self
+    }
+
+    pub fn block(&self) -> Block {
+        let mut genesis = Block::new(0, 0, BlockHash::default());
+        if *self != GenesisParams::default() {
+            genesis.header.extra = borsh::to_vec(self).expect("Borsh serialization failed");
+        }
+        genesis
+    }
+
+    pub fn from_header(genesis: &BlockHeader) -> Option<Self> {
+        if genesis.extra.is_empty() {
+            return Some(GenesisParams::default());
+        }
+        borsh::from_slice(&genesis.extra).ok()
+    }
+
+    pub fn from_chain(ancestors: &[Block]) -> Self {
+        // the params of the chain whose genesis block comes first
+        ancestors
+            .first()
+            .and_then(|genesis| GenesisParams::from_header(&genesis.header))
+            .unwrap_or_default()
+    }
+}
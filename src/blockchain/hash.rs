@@ -0,0 +1,84 @@
+use borsh::{ BorshDeserialize, BorshSerialize };
+use sha2::{ Digest, Sha256 };
+use std::fmt;
+use std::str::FromStr;
+
+/*
+    every hash on the chain is a tagged sha256, sha256(sha256(tag) || sha256(tag) || data),
+    so a block header, a transaction and a merkle node can never hash to the same
+    value even when their encodings happen to be equal. the data is always the
+    borsh encoding, which length prefixes every variable sized field
+*/
+
+pub const BLOCK_HEADER_TAG: &[u8] = b"custom_blockchain/block_header";
+pub const TRANSACTION_TAG: &[u8] = b"custom_blockchain/transaction";
+pub const MERKLE_NODE_TAG: &[u8] = b"custom_blockchain/merkle_node";
+pub const AUTHORITY_VOTE_TAG: &[u8] = b"custom_blockchain/authority_vote";
+pub const PROPOSER_TAG: &[u8] = b"custom_blockchain/proposer";
+pub const CHECKPOINT_VOTE_TAG: &[u8] = b"custom_blockchain/checkpoint_vote";
+
+pub fn tagged_hash(tag: &[u8], data: &[u8]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag);
+    let mut hasher = Sha256::new();
+    hasher.update(tag_hash);
+    hasher.update(tag_hash);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseHashError {
+    InvalidHex,
+    InvalidLength(usize),
+}
+
+impl fmt::Display for ParseHashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseHashError::InvalidHex => write!(f, "invalid hex"),
+            ParseHashError::InvalidLength(len) => write!(f, "expected 32 bytes, got {}", len),
+        }
+    }
+}
+
+// a 32 byte hash shown and parsed as hex
+macro_rules! hash_newtype {
+    ($name:ident) => {
+        #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+        pub struct $name(pub [u8; 32]);
+
+        impl $name {
+            pub fn as_bytes(&self) -> &[u8; 32] {
+                &self.0
+            }
+
+            pub fn from_slice(bytes: &[u8]) -> Option<$name> {
+                Some($name(bytes.try_into().ok()?))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}", hex::encode(self.0))
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseHashError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let bytes = hex::decode(s).map_err(|_| ParseHashError::InvalidHex)?;
+                $name::from_slice(&bytes).ok_or(ParseHashError::InvalidLength(bytes.len()))
+            }
+        }
+    };
+}
+
+hash_newtype!(BlockHash);
+hash_newtype!(TxId);
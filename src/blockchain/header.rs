@@ -0,0 +1,130 @@
+use crate::blockchain::hash::{ tagged_hash, BlockHash, BLOCK_HEADER_TAG };
+use crate::blockchain::genesis::GenesisParams;
+use crate::blockchain::pow::PowAlgorithm;
+use crate::blockchain::timestamp::{ check_time_stamp, header_median_time_past, now };
+use borsh::{ BorshDeserialize, BorshSerialize };
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub height: u64,
+    pub previous_hash: BlockHash,
+    // commits to the block's transactions, see merkle::merkle_root
+    pub merkle_root: [u8; 32],
+    pub time_stamp: u128,
+    // number of leading hex zeroes the block hash needs
+    pub difficulty: u32,
+    pub nonce: i32,
+    // consensus engine data covered by the seal, e.g. authority votes
+    pub extra: Vec<u8>,
+    // consensus engine proof that is not part of what it signs, e.g. a signature
+    pub seal: Vec<u8>,
+}
+
+impl BlockHeader {
+    pub const VERSION: u32 = 1;
+
+    pub fn encode(&self) -> Vec<u8> {
+        // canonical encoding, every field in declaration order
+        borsh::to_vec(self).expect("Borsh serialization failed")
+    }
+
+    pub fn hash(&self) -> BlockHash {
+        BlockHash(tagged_hash(BLOCK_HEADER_TAG, &self.encode()))
+    }
+
+    pub fn signing_hash(&self) -> BlockHash {
+        // the hash a sealer signs, everything but the seal itself
+        let mut unsealed = self.clone();
+        unsealed.seal = Vec::new();
+        unsealed.hash()
+    }
+
+    pub fn print(&self) {
+        println!("version: {}", self.version);
+        println!("height: {}", self.height);
+        println!("timestamp: {:x}", self.time_stamp);
+        println!("difficulty: {}", self.difficulty);
+        println!("nonce: {}", self.nonce);
+        println!("hash: {}", self.hash());
+        println!("previous_hash: {}", self.previous_hash);
+        println!("merkle_root: {}", hex::encode(self.merkle_root));
+        if !self.extra.is_empty() {
+            println!("extra: {}", hex::encode(&self.extra));
+        }
+        if !self.seal.is_empty() {
+            println!("seal: {}", hex::encode(&self.seal));
+        }
+    }
+}
+
+// a chain of block headers without any transaction bodies
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    headers: Vec<BlockHeader>,
+    // taken from the genesis params
+    algorithm: PowAlgorithm,
+}
+
+impl HeaderChain {
+    pub fn new(genesis: BlockHeader) -> Self {
+        // the genesis header is trusted as given
+        HeaderChain {
+            algorithm: GenesisParams::from_header(&genesis).unwrap_or_default().pow_algorithm,
+            headers: vec![genesis],
+        }
+    }
+
+    pub fn trusted(genesis: BlockHeader, headers: Vec<BlockHeader>) -> Self {
+        // headers of blocks a full node has already verified, whatever sealed them,
+        // push would hold signed headers to a proof of work they never had to do
+        let mut chain = HeaderChain::new(genesis);
+        chain.headers.extend(headers);
+        chain
+    }
+
+    pub fn push(&mut self, header: BlockHeader) -> bool {
+        let tip = self.tip();
+        if header.height != tip.height + 1 {
+            println!("header height {} does not follow {}", header.height, tip.height);
+            return false;
+        }
+
+        if header.previous_hash != tip.hash() {
+            println!("header does not extend the tip");
+            return false;
+        }
+
+        if !self.algorithm.meets_difficulty(&header) {
+            println!("header does not meet its difficulty");
+            return false;
+        }
+
+        if !check_time_stamp(header_median_time_past(&self.headers), &header, now()) {
+            return false;
+        }
+
+        self.headers.push(header);
+        true
+    }
+
+    pub fn tip(&self) -> &BlockHeader {
+        &self.headers[self.headers.len() - 1]
+    }
+
+    pub fn get(&self, height: u64) -> Option<&BlockHeader> {
+        self.headers.get(height as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BlockHeader> {
+        self.headers.iter()
+    }
+}
@@ -0,0 +1,161 @@
+use crate::blockchain::hash::TxId;
+use crate::blockchain::transaction::{ HtlcLock, Transaction, TransactionKind };
+use sha2::{ Digest, Sha256 };
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HtlcStatus {
+    Locked,
+    Claimed,
+    Refunded,
+}
+
+#[derive(Debug, Clone)]
+pub struct Htlc {
+    pub id: TxId,
+    pub sender_address: Vec<u8>,
+    pub lock: HtlcLock,
+    pub status: HtlcStatus,
+}
+
+impl Htlc {
+    pub fn matches_preimage(&self, preimage: &[u8]) -> bool {
+        Sha256::digest(preimage).to_vec() == self.lock.hash_lock
+    }
+
+    pub fn can_claim(&self, claimer: &[u8], preimage: &[u8], height: u64) -> bool {
+        self.status == HtlcStatus::Locked &&
+            claimer == self.lock.recipient_address.as_slice() &&
+            height < self.lock.expiry_height &&
+            self.matches_preimage(preimage)
+    }
+
+    pub fn can_refund(&self, refunder: &[u8], height: u64) -> bool {
+        self.status == HtlcStatus::Locked &&
+            refunder == self.sender_address.as_slice() &&
+            height >= self.lock.expiry_height
+    }
+
+    pub fn can_settle(&self, tx: &Transaction, height: u64) -> bool {
+        // whether tx, included at height, may claim or refund this htlc
+        match &tx.kind {
+            TransactionKind::HtlcClaim { htlc_id, preimage } => {
+                *htlc_id == self.id && self.can_claim(&tx.sender_address, preimage, height)
+            }
+            TransactionKind::HtlcRefund { htlc_id } => *htlc_id == self.id && self.can_refund(&tx.sender_address, height),
+            _ => false,
+        }
+    }
+}
+
+/*
+    apply the htlc effect of a transaction included at the given height to the
+    set of known htlcs, returns false if the transaction spends an htlc it is not
+    allowed to (unknown, already settled, wrong party, wrong preimage or timing)
+*/
+pub fn apply_htlc(htlcs: &mut HashMap<TxId, Htlc>, tx: &Transaction, height: u64) -> bool {
+    match &tx.kind {
+        TransactionKind::Transfer |
+        TransactionKind::Stake { .. } |
+        TransactionKind::Unstake { .. } |
+        TransactionKind::Slash(_) |
+        TransactionKind::Coinbase { .. } => true,
+        TransactionKind::HtlcCreate(lock) => {
+            let id = tx.id();
+            htlcs.insert(id, Htlc {
+                id,
+                sender_address: tx.sender_address.clone(),
+                lock: lock.clone(),
+                status: HtlcStatus::Locked,
+            });
+            true
+        }
+        TransactionKind::HtlcClaim { htlc_id, preimage } => {
+            match htlcs.get_mut(htlc_id) {
+                Some(htlc) if htlc.can_claim(&tx.sender_address, preimage, height) => {
+                    htlc.status = HtlcStatus::Claimed;
+                    true
+                }
+                _ => false,
+            }
+        }
+        TransactionKind::HtlcRefund { htlc_id } => {
+            match htlcs.get_mut(htlc_id) {
+                Some(htlc) if htlc.can_refund(&tx.sender_address, height) => {
+                    htlc.status = HtlcStatus::Refunded;
+                    true
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPIRY: u64 = 10;
+
+    fn create(htlcs: &mut HashMap<TxId, Htlc>) -> TxId {
+        // alice locks 5 for bob until EXPIRY behind the preimage "secret"
+        let mut tx = Transaction::new_batch(b"alice".to_vec(), Vec::new());
+        tx.kind = TransactionKind::HtlcCreate(HtlcLock {
+            recipient_address: b"bob".to_vec(),
+            value: 5,
+            hash_lock: Sha256::digest(b"secret").to_vec(),
+            expiry_height: EXPIRY,
+        });
+        assert!(apply_htlc(htlcs, &tx, 1));
+        tx.id()
+    }
+
+    fn claim(claimer: &[u8], htlc_id: TxId, preimage: &[u8]) -> Transaction {
+        let mut tx = Transaction::new_batch(claimer.to_vec(), Vec::new());
+        tx.kind = TransactionKind::HtlcClaim { htlc_id, preimage: preimage.to_vec() };
+        tx
+    }
+
+    fn refund(refunder: &[u8], htlc_id: TxId) -> Transaction {
+        let mut tx = Transaction::new_batch(refunder.to_vec(), Vec::new());
+        tx.kind = TransactionKind::HtlcRefund { htlc_id };
+        tx
+    }
+
+    #[test]
+    fn recipient_claims_with_the_preimage_before_expiry() {
+        let mut htlcs = HashMap::new();
+        let id = create(&mut htlcs);
+
+        assert!(!apply_htlc(&mut htlcs, &claim(b"bob", id, b"guess"), EXPIRY - 1));
+        assert!(!apply_htlc(&mut htlcs, &claim(b"alice", id, b"secret"), EXPIRY - 1));
+        assert!(!apply_htlc(&mut htlcs, &claim(b"bob", id, b"secret"), EXPIRY));
+        assert!(apply_htlc(&mut htlcs, &claim(b"bob", id, b"secret"), EXPIRY - 1));
+        assert_eq!(htlcs[&id].status, HtlcStatus::Claimed);
+    }
+
+    #[test]
+    fn sender_refunds_from_expiry_on() {
+        let mut htlcs = HashMap::new();
+        let id = create(&mut htlcs);
+
+        assert!(!apply_htlc(&mut htlcs, &refund(b"alice", id), EXPIRY - 1));
+        assert!(!apply_htlc(&mut htlcs, &refund(b"bob", id), EXPIRY));
+        assert!(apply_htlc(&mut htlcs, &refund(b"alice", id), EXPIRY));
+        assert_eq!(htlcs[&id].status, HtlcStatus::Refunded);
+    }
+
+    #[test]
+    fn an_htlc_settles_once() {
+        let mut htlcs = HashMap::new();
+        let id = create(&mut htlcs);
+        assert!(apply_htlc(&mut htlcs, &claim(b"bob", id, b"secret"), 2));
+        assert!(!apply_htlc(&mut htlcs, &claim(b"bob", id, b"secret"), 3));
+        assert!(!apply_htlc(&mut htlcs, &refund(b"alice", id), EXPIRY));
+
+        let id = create(&mut htlcs);
+        assert!(!apply_htlc(&mut htlcs, &claim(b"bob", TxId::default(), b"secret"), 2));
+        assert!(apply_htlc(&mut htlcs, &refund(b"alice", id), EXPIRY));
+        assert!(!apply_htlc(&mut htlcs, &refund(b"alice", id), EXPIRY + 1));
+    }
+}
@@ -0,0 +1,248 @@
+use crate::blockchain::hash::{ BlockHash, TxId };
+use crate::blockchain::htlc::{ apply_htlc, Htlc, HtlcStatus };
+use crate::blockchain::stake::StakeLedger;
+use crate::blockchain::transaction::{ Transaction, TransactionKind };
+use crate::blockchain::{ Block, Serialization };
+use std::collections::{ BTreeSet, HashMap };
+
+// where a confirmed transaction sits in the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxLocation {
+    pub height: u64,
+    pub position: usize,
+}
+
+// a confirmed transaction together with the block that confirmed it
+#[derive(Debug, Clone)]
+pub struct TransactionReceipt {
+    pub id: TxId,
+    pub height: u64,
+    pub position: usize,
+    pub block_hash: BlockHash,
+    pub time_stamp: u128,
+    // 1 when the transaction is in the tip block
+    pub confirmations: u64,
+    pub transaction: Transaction,
+}
+
+#[derive(Debug, Default)]
+pub struct TxIndex {
+    by_id: HashMap<TxId, TxLocation>,
+    // every transaction an address sent or received, in chain order
+    by_address: HashMap<Vec<u8>, Vec<TxId>>,
+}
+
+impl TxIndex {
+    pub fn new() -> Self {
+        TxIndex::default()
+    }
+
+    pub fn add_block(&mut self, block: &Block) {
+        for (position, tx) in block.transactions.iter().enumerate() {
+            let transaction = Transaction::deserialization(tx.clone());
+            let id = transaction.id();
+            self.by_id.insert(id, TxLocation {
+                height: block.height(),
+                position,
+            });
+
+            for address in transaction.addresses() {
+                let history = self.by_address.entry(address.to_vec()).or_default();
+                // an address paying itself is listed once
+                if history.last() != Some(&id) {
+                    history.push(id);
+                }
+            }
+        }
+    }
+
+    pub fn remove_block(&mut self, block: &Block) {
+        // undoes add_block for the tip block, its entries are the newest ones
+        for tx in block.transactions.iter().rev() {
+            let transaction = Transaction::deserialization(tx.clone());
+            let id = transaction.id();
+            self.by_id.remove(&id);
+            for address in transaction.addresses() {
+                if let Some(history) = self.by_address.get_mut(address) {
+                    if history.last() == Some(&id) {
+                        history.pop();
+                    }
+                    if history.is_empty() {
+                        self.by_address.remove(address);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn location(&self, id: &TxId) -> Option<TxLocation> {
+        self.by_id.get(id).copied()
+    }
+
+    pub fn history_len(&self, address: &[u8]) -> usize {
+        self.by_address.get(address).map_or(0, |history| history.len())
+    }
+
+    pub fn history(&self, address: &[u8], skip: usize, limit: usize) -> Vec<TxId> {
+        // newest first, so the first page holds the latest activity
+        match self.by_address.get(address) {
+            Some(history) => history.iter().rev().skip(skip).take(limit).copied().collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/*
+    the confirmed state every pending transaction is checked against: balances,
+    used sequences, htlcs and stakes. it moves with the chain one block at a
+    time like TxIndex, so checking a transaction does not replay the chain
+*/
+#[derive(Debug, Default)]
+pub struct StateIndex {
+    balances: HashMap<Vec<u8>, i64>,
+    // every sequence a sender has used on chain
+    sequences: HashMap<Vec<u8>, BTreeSet<u64>>,
+    htlcs: HashMap<TxId, Htlc>,
+    stakes: StakeLedger,
+    // one per block, what add_block has to take back in remove_block
+    undo: Vec<BlockUndo>,
+}
+
+#[derive(Debug, Default)]
+struct BlockUndo {
+    // what the block added to each balance
+    balances: HashMap<Vec<u8>, i64>,
+    // htlcs the block created (None) or settled (the status before), in block order
+    htlcs: Vec<(TxId, Option<HtlcStatus>)>,
+    // the ledger before the block, only kept when the block changed it
+    stakes: Option<StakeLedger>,
+}
+
+impl StateIndex {
+    pub fn new(stakes: StakeLedger) -> Self {
+        // stakes holds whatever the consensus engine bonded at genesis
+        StateIndex {
+            stakes,
+            ..StateIndex::default()
+        }
+    }
+
+    pub fn add_block(&mut self, block: &Block) {
+        // the block has been verified, every transaction in it applies
+        let height = block.height();
+        let mut undo = BlockUndo::default();
+        for tx in block.transactions.iter() {
+            let transaction = Transaction::deserialization(tx.clone());
+            let sender = &transaction.sender_address;
+
+            for output in transaction.outputs.iter() {
+                *undo.balances.entry(output.recipient_address.clone()).or_default() += output.value as i64;
+            }
+            let mut change = -(transaction.spent_value() as i64);
+            match &transaction.kind {
+                // a settled htlc pays its value to whoever claimed or refunded it
+                | TransactionKind::HtlcClaim { htlc_id, .. }
+                | TransactionKind::HtlcRefund { htlc_id } => {
+                    if let Some(htlc) = self.htlcs.get(htlc_id) {
+                        change += htlc.lock.value as i64;
+                        undo.htlcs.push((*htlc_id, Some(htlc.status)));
+                    }
+                }
+                TransactionKind::HtlcCreate(_) => undo.htlcs.push((transaction.id(), None)),
+                // only stake that came out of a balance goes back to one
+                TransactionKind::Unstake { .. } => {
+                    change += self.stakes.released(&transaction) as i64;
+                }
+                _ => {}
+            }
+            *undo.balances.entry(sender.clone()).or_default() += change;
+
+            if matches!(transaction.kind, TransactionKind::Stake { .. } | TransactionKind::Unstake { .. } | TransactionKind::Slash(_)) && undo.stakes.is_none() {
+                undo.stakes = Some(self.stakes.clone());
+            }
+            apply_htlc(&mut self.htlcs, &transaction, height);
+            self.stakes.apply(&transaction);
+
+            // the coinbase has no sender whose sequence it could use
+            if !matches!(transaction.kind, TransactionKind::Coinbase { .. }) {
+                self.sequences.entry(sender.clone()).or_default().insert(transaction.sequence);
+            }
+        }
+
+        for (address, change) in undo.balances.iter() {
+            *self.balances.entry(address.clone()).or_default() += change;
+        }
+        self.undo.push(undo);
+    }
+
+    pub fn remove_block(&mut self, block: &Block) {
+        // undoes add_block for the tip block
+        let undo = match self.undo.pop() {
+            Some(undo) => undo,
+            None => {
+                return;
+            }
+        };
+
+        for (address, change) in undo.balances.iter() {
+            if let Some(balance) = self.balances.get_mut(address) {
+                *balance -= change;
+                if *balance == 0 {
+                    self.balances.remove(address);
+                }
+            }
+        }
+        for (id, status) in undo.htlcs.into_iter().rev() {
+            match status {
+                Some(status) => {
+                    if let Some(htlc) = self.htlcs.get_mut(&id) {
+                        htlc.status = status;
+                    }
+                }
+                None => {
+                    self.htlcs.remove(&id);
+                }
+            }
+        }
+        if let Some(stakes) = undo.stakes {
+            self.stakes = stakes;
+        }
+
+        for tx in block.transactions.iter() {
+            let transaction = Transaction::deserialization(tx.clone());
+            if matches!(transaction.kind, TransactionKind::Coinbase { .. }) {
+                continue;
+            }
+            if let Some(sequences) = self.sequences.get_mut(&transaction.sender_address) {
+                sequences.remove(&transaction.sequence);
+                if sequences.is_empty() {
+                    self.sequences.remove(&transaction.sender_address);
+                }
+            }
+        }
+    }
+
+    pub fn balance(&self, address: &[u8]) -> i64 {
+        self.balances.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn has_sequence(&self, sender: &[u8], sequence: u64) -> bool {
+        self.sequences.get(sender).is_some_and(|sequences| sequences.contains(&sequence))
+    }
+
+    pub fn last_sequence(&self, sender: &[u8]) -> Option<u64> {
+        self.sequences.get(sender).and_then(|sequences| sequences.last().copied())
+    }
+
+    pub fn htlc(&self, id: &TxId) -> Option<&Htlc> {
+        self.htlcs.get(id)
+    }
+
+    pub fn htlcs(&self) -> &HashMap<TxId, Htlc> {
+        &self.htlcs
+    }
+
+    pub fn stakes(&self) -> &StakeLedger {
+        &self.stakes
+    }
+}
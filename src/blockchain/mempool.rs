@@ -0,0 +1,548 @@
+use crate::blockchain::events::DropReason;
+use crate::blockchain::hash::TxId;
+use crate::blockchain::transaction::Transaction;
+use crate::blockchain::Serialization;
+use std::collections::{ BTreeMap, HashMap, HashSet };
+use std::time::{ Duration, Instant };
+
+#[derive(Debug, Clone)]
+pub struct MempoolConfig {
+    // upper bound on the serialized size of all pending transactions
+    pub max_bytes: usize,
+    // pending transactions that could have gone into a block for this long
+    // are dropped, a locked transaction waits for its lock time first
+    pub expiry: Duration,
+    // how many pending transactions a single sender may have
+    pub max_per_sender: usize,
+    // how many unconfirmed ancestors a pending transaction may depend on
+    pub max_ancestors: usize,
+    // a replacement has to pay at least this much, and at least a tenth of
+    // the replaced fee, more than the fees of everything it evicts
+    pub min_fee_bump: u64,
+    // how many times one sequence number can be replaced while pending
+    pub max_replacements: u32,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        MempoolConfig {
+            max_bytes: 1_000_000,
+            expiry: Duration::from_secs(60 * 60 * 24),
+            max_per_sender: 25,
+            max_ancestors: 25,
+            min_fee_bump: 1,
+            max_replacements: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MempoolEntry {
+    pub id: TxId,
+    pub transaction: Transaction,
+    pub size: usize,
+    pub received: Instant,
+    // when the lock time passed, expiry counts from here, None while locked
+    pub eligible: Option<Instant>,
+    // how many earlier versions of this sequence number were replaced
+    pub replacements: u32,
+    // pending transactions whose outputs this one spends
+    pub parents: Vec<TxId>,
+    // every pending transaction this one depends on, worked out when it enters
+    // the pool, some may have left it since
+    ancestors: HashSet<TxId>,
+    // position in arrival order, set by the pool
+    arrival: u64,
+}
+
+impl MempoolEntry {
+    pub fn new(transaction: Transaction, parents: Vec<TxId>) -> Self {
+        let size = transaction.serialization().len();
+        let received = Instant::now();
+        MempoolEntry {
+            id: transaction.id(),
+            eligible: if transaction.lock_time.is_none() { Some(received) } else { None },
+            transaction,
+            size,
+            received,
+            replacements: 0,
+            parents,
+            ancestors: HashSet::new(),
+            arrival: 0,
+        }
+    }
+
+    pub fn fee(&self) -> u64 {
+        self.transaction.fee
+    }
+
+    pub fn has_lower_fee_rate(&self, other: &MempoolEntry) -> bool {
+        // compare fee / size without dividing
+        (self.fee() as u128) * (other.size as u128) < (other.fee() as u128) * (self.size as u128)
+    }
+}
+
+// a pending transaction together with the ancestors that have to be
+// confirmed before it, scored by their combined fee rate
+#[derive(Debug)]
+pub struct MempoolPackage<'a> {
+    // ancestors first, the transaction the package was built for last
+    pub entries: Vec<&'a MempoolEntry>,
+    pub fee: u64,
+    pub size: usize,
+}
+
+impl MempoolPackage<'_> {
+    pub fn has_higher_fee_rate(&self, other: &MempoolPackage) -> bool {
+        (self.fee as u128) * (other.size as u128) > (other.fee as u128) * (self.size as u128)
+    }
+}
+
+#[derive(Debug)]
+pub struct Mempool {
+    config: MempoolConfig,
+    entries: HashMap<TxId, MempoolEntry>,
+    // arrival number to id, parents always arrive before their children
+    order: BTreeMap<u64, TxId>,
+    next_arrival: u64,
+    total_bytes: usize,
+    // entries the pool let go of on its own, see take_dropped
+    dropped: Vec<(MempoolEntry, DropReason)>,
+}
+
+impl Mempool {
+    pub fn new(config: MempoolConfig) -> Self {
+        Mempool {
+            config,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            next_arrival: 0,
+            total_bytes: 0,
+            dropped: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &MempoolConfig {
+        &self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &MempoolEntry> {
+        // in arrival order
+        self.order.values().filter_map(|id| self.entries.get(id))
+    }
+
+    pub fn get(&self, id: &TxId) -> Option<&MempoolEntry> {
+        self.entries.get(id)
+    }
+
+    pub fn contains(&self, id: &TxId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn sender_count(&self, sender: &[u8]) -> usize {
+        self.entries
+            .values()
+            .filter(|entry| entry.transaction.sender_address == sender)
+            .count()
+    }
+
+    pub fn pending_spent(&self, sender: &[u8]) -> u64 {
+        // value the sender has already committed to pending transactions
+        self.entries
+            .values()
+            .filter(|entry| entry.transaction.sender_address == sender)
+            .map(|entry| entry.transaction.spent_value())
+            .sum()
+    }
+
+    pub fn pending_received(&self, recipient: &[u8]) -> Vec<(&MempoolEntry, u64)> {
+        // pending transactions paying the recipient, in arrival order
+        self.entries()
+            .filter_map(|entry| {
+                let value: u64 = entry.transaction.outputs
+                    .iter()
+                    .filter(|output| output.recipient_address == recipient)
+                    .map(|output| output.value)
+                    .sum();
+                if value > 0 { Some((entry, value)) } else { None }
+            })
+            .collect()
+    }
+
+    pub fn find_sequence(&self, sender: &[u8], sequence: u64) -> Option<&MempoolEntry> {
+        self.entries
+            .values()
+            .find(|entry| entry.transaction.sender_address == sender && entry.transaction.sequence == sequence)
+    }
+
+    pub fn ancestors(&self, id: &TxId) -> Vec<&MempoolEntry> {
+        // every pending transaction the given one depends on, in arrival order
+        let mut ancestors: Vec<&MempoolEntry> = match self.get(id) {
+            Some(entry) => entry.ancestors
+                .iter()
+                .filter_map(|ancestor| self.get(ancestor))
+                .collect(),
+            None => Vec::new(),
+        };
+        ancestors.sort_by_key(|ancestor| ancestor.arrival);
+        ancestors
+    }
+
+    pub fn descendants(&self, id: &TxId) -> Vec<&MempoolEntry> {
+        // every pending transaction that depends on the given one, in arrival order
+        let mut descendants = HashSet::<&TxId>::new();
+        descendants.insert(id);
+        let mut result = Vec::<&MempoolEntry>::new();
+        for entry in self.entries() {
+            if entry.parents.iter().any(|parent| descendants.contains(parent)) {
+                descendants.insert(&entry.id);
+                result.push(entry);
+            }
+        }
+        result
+    }
+
+    pub fn package(&self, id: &TxId, confirmed: &HashSet<TxId>) -> Option<MempoolPackage<'_>> {
+        // the transaction and its ancestors that are not in the confirmed set yet
+        let entry = self.get(id)?;
+        let mut entries: Vec<&MempoolEntry> = self
+            .ancestors(id)
+            .into_iter()
+            .filter(|ancestor| !confirmed.contains(&ancestor.id))
+            .collect();
+        entries.push(entry);
+
+        Some(MempoolPackage {
+            fee: entries
+                .iter()
+                .map(|entry| entry.fee())
+                .sum(),
+            size: entries
+                .iter()
+                .map(|entry| entry.size)
+                .sum(),
+            entries,
+        })
+    }
+
+    pub fn insert(&mut self, transaction: Transaction, parents: Vec<TxId>) -> bool {
+        self.expire(Instant::now());
+        self.insert_entry(MempoolEntry::new(transaction, parents))
+    }
+
+    pub fn replace(&mut self, transaction: Transaction, parents: Vec<TxId>) -> bool {
+        // replace-by-fee: a pending transaction is swapped for one from the same
+        // sender with the same sequence number paying a strictly higher fee
+        self.expire(Instant::now());
+
+        let old = match self.find_sequence(&transaction.sender_address, transaction.sequence) {
+            Some(old) => old.clone(),
+            None => {
+                println!("no pending transaction to replace");
+                return false;
+            }
+        };
+
+        if old.replacements >= self.config.max_replacements {
+            println!("transaction has been replaced too many times");
+            return false;
+        }
+
+        // the replacement evicts whatever spends the old transaction as well, so it
+        // has to outbid all of them, not just the transaction with its sequence
+        let replaced_fee = self
+            .descendants(&old.id)
+            .iter()
+            .fold(old.fee(), |fee, entry| fee.saturating_add(entry.fee()));
+        let min_bump = self.config.min_fee_bump.max(old.fee() / 10);
+        if transaction.fee < replaced_fee.saturating_add(min_bump) {
+            println!("replacement fee must be at least {}", replaced_fee.saturating_add(min_bump));
+            return false;
+        }
+
+        let mut entry = MempoolEntry::new(transaction, parents);
+        entry.replacements = old.replacements + 1;
+        // whatever spent the replaced transaction's outputs goes with it
+        let removed = self.remove_with_descendants(&old.id);
+        if !self.insert_entry(entry) {
+            // keep the original if the replacement does not fit
+            self.restore(removed);
+            return false;
+        }
+        self.dropped.extend(removed.into_iter().map(|entry| (entry, DropReason::Replaced)));
+
+        true
+    }
+
+    fn insert_entry(&mut self, entry: MempoolEntry) -> bool {
+        if self.contains(&entry.id) {
+            println!("transaction is already in the mempool");
+            return false;
+        }
+
+        if self.sender_count(&entry.transaction.sender_address) >= self.config.max_per_sender {
+            println!("sender has too many pending transactions");
+            return false;
+        }
+
+        if entry.parents.iter().any(|parent| !self.contains(parent)) {
+            println!("transaction depends on a transaction that is not pending");
+            return false;
+        }
+
+        let ancestors = self.ancestors_of(&entry.parents);
+        if ancestors.len() > self.config.max_ancestors {
+            println!("transaction has too many pending ancestors");
+            return false;
+        }
+
+        if entry.size > self.config.max_bytes {
+            println!("transaction is larger than the mempool");
+            return false;
+        }
+
+        // make room by evicting the cheapest transactions, but only ones paying
+        // a lower fee rate than the newcomer, never its own ancestors
+        let mut by_fee_rate: Vec<&MempoolEntry> = self.entries.values().collect();
+        by_fee_rate.sort_by(|a, b| {
+            (a.fee() as u128 * b.size as u128).cmp(&(b.fee() as u128 * a.size as u128))
+        });
+        let mut evict = HashSet::<TxId>::new();
+        let mut freed = 0;
+        for candidate in by_fee_rate {
+            if self.total_bytes - freed + entry.size <= self.config.max_bytes {
+                break;
+            }
+
+            if evict.contains(&candidate.id) || ancestors.contains(&candidate.id) {
+                continue;
+            }
+
+            if !candidate.has_lower_fee_rate(&entry) {
+                println!("mempool is full and the transaction fee is too low");
+                return false;
+            }
+
+            // evicting a transaction also evicts everything that depends on it
+            for removed in std::iter::once(candidate).chain(self.descendants(&candidate.id)) {
+                if evict.insert(removed.id) {
+                    freed += removed.size;
+                }
+            }
+        }
+
+        if self.total_bytes - freed + entry.size > self.config.max_bytes {
+            println!("mempool is full and the transaction fee is too low");
+            return false;
+        }
+
+        for id in evict.iter() {
+            if let Some(removed) = self.remove(id) {
+                println!("evicted transaction {} from the mempool", id);
+                self.dropped.push((removed, DropReason::Evicted));
+            }
+        }
+
+        self.add(entry);
+        true
+    }
+
+    fn ancestors_of(&self, parents: &[TxId]) -> HashSet<TxId> {
+        // the parents and everything they depend on that is still pending
+        let mut ancestors = HashSet::<TxId>::new();
+        for parent in parents.iter() {
+            if let Some(entry) = self.get(parent) {
+                ancestors.insert(*parent);
+                ancestors.extend(entry.ancestors.iter().filter(|ancestor| self.contains(ancestor)));
+            }
+        }
+        ancestors
+    }
+
+    fn add(&mut self, mut entry: MempoolEntry) {
+        // the newest arrival, its parents are already in the pool
+        entry.ancestors = self.ancestors_of(&entry.parents);
+        entry.arrival = self.next_arrival;
+        self.next_arrival += 1;
+        self.total_bytes += entry.size;
+        self.order.insert(entry.arrival, entry.id);
+        self.entries.insert(entry.id, entry);
+    }
+
+    pub fn remove(&mut self, id: &TxId) -> Option<MempoolEntry> {
+        let entry = self.entries.remove(id)?;
+        self.order.remove(&entry.arrival);
+        self.total_bytes -= entry.size;
+        Some(entry)
+    }
+
+    pub fn remove_with_descendants(&mut self, id: &TxId) -> Vec<MempoolEntry> {
+        let mut ids: Vec<TxId> = vec![*id];
+        ids.extend(self.descendants(id).into_iter().map(|entry| entry.id));
+        ids.iter()
+            .filter_map(|id| self.remove(id))
+            .collect()
+    }
+
+    pub fn mark_eligible(&mut self, height: u64, time_stamp: u128) {
+        // starts the expiry clock of locked transactions that a block at
+        // height with time_stamp could now include
+        let now = Instant::now();
+        for entry in self.entries.values_mut() {
+            if entry.eligible.is_none() && entry.transaction.is_final(height, time_stamp) {
+                entry.eligible = Some(now);
+            }
+        }
+    }
+
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let expiry = self.config.expiry;
+        let stale: Vec<TxId> = self.entries()
+            .filter(|entry| entry.eligible.is_some_and(|eligible| now.duration_since(eligible) >= expiry))
+            .map(|entry| entry.id)
+            .collect();
+
+        let mut expired = 0;
+        for id in stale {
+            let removed = self.remove_with_descendants(&id);
+            expired += removed.len();
+            self.dropped.extend(removed.into_iter().map(|entry| (entry, DropReason::Expired)));
+        }
+        expired
+    }
+
+    pub fn take_dropped(&mut self) -> Vec<(MempoolEntry, DropReason)> {
+        // what was evicted, expired or replaced since the last call
+        std::mem::take(&mut self.dropped)
+    }
+
+    pub fn by_fee_rate(&self) -> Vec<&MempoolEntry> {
+        // highest fee rate first, ties keep arrival order
+        let mut entries: Vec<&MempoolEntry> = self.entries().collect();
+        entries.sort_by(|a, b| {
+            (b.fee() as u128 * a.size as u128).cmp(&(a.fee() as u128 * b.size as u128))
+        });
+        entries
+    }
+
+    pub fn drain(&mut self) -> Vec<MempoolEntry> {
+        // everything in arrival order, leaving the pool empty
+        self.total_bytes = 0;
+        let order = std::mem::take(&mut self.order);
+        order
+            .into_values()
+            .filter_map(|id| self.entries.remove(&id))
+            .collect()
+    }
+
+    pub fn restore(&mut self, entries: Vec<MempoolEntry>) {
+        // put back entries that were taken out, keeping when they were
+        // received, they go behind everything still pending
+        for entry in entries {
+            self.add(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::transaction::LockTime;
+
+    fn transfer(sender: &str, sequence: u64, fee: u64) -> Transaction {
+        let mut tx = Transaction::new(sender.as_bytes().to_vec(), b"recipient".to_vec(), 1);
+        tx.sequence = sequence;
+        tx.fee = fee;
+        tx
+    }
+
+    #[test]
+    fn expiry_waits_for_lock_time() {
+        let mut mempool = Mempool::new(MempoolConfig { expiry: Duration::ZERO, ..MempoolConfig::default() });
+        let mut locked = transfer("sender", 0, 1);
+        locked.lock_time = Some(LockTime::Height(100));
+        assert!(mempool.insert(locked.clone(), Vec::new()));
+
+        // long past expiry, but it could not have been mined yet
+        assert_eq!(mempool.expire(Instant::now()), 0);
+        mempool.mark_eligible(99, 0);
+        assert_eq!(mempool.expire(Instant::now()), 0);
+
+        mempool.mark_eligible(100, 0);
+        assert_eq!(mempool.expire(Instant::now()), 1);
+        assert!(!mempool.contains(&locked.id()));
+    }
+
+    #[test]
+    fn replacement_has_to_bump_the_fee() {
+        let mut mempool = Mempool::new(MempoolConfig { min_fee_bump: 2, ..MempoolConfig::default() });
+        let original = transfer("sender", 0, 30);
+        assert!(mempool.insert(original.clone(), Vec::new()));
+
+        // at least max(min_fee_bump, a tenth of the old fee) more
+        assert!(!mempool.replace(transfer("sender", 0, 32), Vec::new()));
+        assert!(mempool.contains(&original.id()));
+
+        let replacement = transfer("sender", 0, 33);
+        assert!(mempool.replace(replacement.clone(), Vec::new()));
+        assert!(!mempool.contains(&original.id()));
+        assert_eq!(mempool.get(&replacement.id()).unwrap().replacements, 1);
+        assert!(
+            mempool
+                .take_dropped()
+                .iter()
+                .any(|(entry, reason)| entry.id == original.id() && *reason == DropReason::Replaced)
+        );
+    }
+
+    #[test]
+    fn replacement_count_is_limited() {
+        let mut mempool = Mempool::new(MempoolConfig { max_replacements: 2, ..MempoolConfig::default() });
+        assert!(mempool.insert(transfer("sender", 0, 1), Vec::new()));
+        assert!(mempool.replace(transfer("sender", 0, 2), Vec::new()));
+        assert!(mempool.replace(transfer("sender", 0, 3), Vec::new()));
+        assert!(!mempool.replace(transfer("sender", 0, 100), Vec::new()));
+    }
+
+    #[test]
+    fn replacement_drops_descendants() {
+        let mut mempool = Mempool::new(MempoolConfig::default());
+        let parent = transfer("sender", 0, 1);
+        let child = transfer("recipient", 0, 1);
+        assert!(mempool.insert(parent.clone(), Vec::new()));
+        assert!(mempool.insert(child.clone(), vec![parent.id()]));
+
+        assert!(mempool.replace(transfer("sender", 0, 5), Vec::new()));
+        assert!(!mempool.contains(&child.id()));
+        assert_eq!(mempool.len(), 1);
+    }
+
+    #[test]
+    fn replacement_has_to_outbid_evicted_descendants() {
+        let mut mempool = Mempool::new(MempoolConfig::default());
+        let parent = transfer("sender", 0, 1);
+        let child = transfer("recipient", 0, 50);
+        assert!(mempool.insert(parent.clone(), Vec::new()));
+        assert!(mempool.insert(child.clone(), vec![parent.id()]));
+
+        // a small bump over the parent would throw out someone else's well paying child
+        assert!(!mempool.replace(transfer("sender", 0, 2), Vec::new()));
+        assert!(!mempool.replace(transfer("sender", 0, 51), Vec::new()));
+        assert!(mempool.contains(&child.id()));
+
+        assert!(mempool.replace(transfer("sender", 0, 52), Vec::new()));
+        assert!(!mempool.contains(&child.id()));
+    }
+}
@@ -0,0 +1,146 @@
+use crate::blockchain::hash::{ tagged_hash, TxId, MERKLE_NODE_TAG, TRANSACTION_TAG };
+
+/*
+    leaves are the ids of the transactions, every level hashes pairs of nodes
+    together, an odd node at the end of a level is paired with itself. a block
+    without transactions has a root of 32 zero bytes
+*/
+
+pub fn leaf_hash(tx: &[u8]) -> [u8; 32] {
+    // the same as the transaction id
+    tagged_hash(TRANSACTION_TAG, tx)
+}
+
+pub fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    tagged_hash(MERKLE_NODE_TAG, &[left.as_slice(), right.as_slice()].concat())
+}
+
+pub fn merkle_root(transactions: &[Vec<u8>]) -> [u8; 32] {
+    if transactions.is_empty() {
+        return [0_u8; 32];
+    }
+
+    let mut level: Vec<[u8; 32]> = transactions
+        .iter()
+        .map(|tx| leaf_hash(tx))
+        .collect();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| node_hash(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+    }
+
+    level[0]
+}
+
+// the sibling hashes on the way from one leaf up to the root
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl MerkleProof {
+    pub fn verify(&self, transaction: &[u8], root: &[u8; 32]) -> bool {
+        self.verify_id(&TxId(leaf_hash(transaction)), root)
+    }
+
+    pub fn verify_id(&self, id: &TxId, root: &[u8; 32]) -> bool {
+        let mut hash = id.0;
+        let mut index = self.index;
+        for sibling in self.siblings.iter() {
+            // even positions are the left node of their pair
+            hash = if index.is_multiple_of(2) { node_hash(&hash, sibling) } else { node_hash(sibling, &hash) };
+            index /= 2;
+        }
+        hash == *root
+    }
+}
+
+pub fn merkle_proof(transactions: &[Vec<u8>], index: usize) -> Option<MerkleProof> {
+    if index >= transactions.len() {
+        return None;
+    }
+
+    let mut level: Vec<[u8; 32]> = transactions
+        .iter()
+        .map(|tx| leaf_hash(tx))
+        .collect();
+    let mut position = index;
+    let mut siblings = Vec::<[u8; 32]>::new();
+    while level.len() > 1 {
+        let sibling = position ^ 1;
+        // an odd node at the end of a level is paired with itself
+        siblings.push(*level.get(sibling).unwrap_or(&level[position]));
+        level = level
+            .chunks(2)
+            .map(|pair| node_hash(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+        position /= 2;
+    }
+
+    Some(MerkleProof {
+        index,
+        siblings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transactions(count: u8) -> Vec<Vec<u8>> {
+        (0..count).map(|tx| vec![tx; 3]).collect()
+    }
+
+    #[test]
+    fn every_leaf_proves_for_odd_and_even_counts() {
+        for count in 1..=9 {
+            let transactions = transactions(count);
+            let root = merkle_root(&transactions);
+            for (index, tx) in transactions.iter().enumerate() {
+                let proof = merkle_proof(&transactions, index).unwrap();
+                assert!(proof.verify(tx, &root), "leaf {} of {}", index, count);
+            }
+            assert_eq!(merkle_proof(&transactions, transactions.len()), None);
+        }
+    }
+
+    #[test]
+    fn odd_node_is_paired_with_itself() {
+        let transactions = transactions(3);
+        let (a, b, c) = (leaf_hash(&transactions[0]), leaf_hash(&transactions[1]), leaf_hash(&transactions[2]));
+        assert_eq!(merkle_root(&transactions), node_hash(&node_hash(&a, &b), &node_hash(&c, &c)));
+        assert_eq!(merkle_proof(&transactions, 2).unwrap().siblings, vec![c, node_hash(&a, &b)]);
+    }
+
+    #[test]
+    fn single_leaf_is_its_own_root() {
+        let transactions = transactions(1);
+        let root = merkle_root(&transactions);
+        assert_eq!(root, leaf_hash(&transactions[0]));
+        let proof = merkle_proof(&transactions, 0).unwrap();
+        assert!(proof.siblings.is_empty());
+        assert!(proof.verify(&transactions[0], &root));
+        assert!(!proof.verify(b"another transaction", &root));
+    }
+
+    #[test]
+    fn tampered_proofs_fail() {
+        let transactions = transactions(5);
+        let root = merkle_root(&transactions);
+        let proof = merkle_proof(&transactions, 1).unwrap();
+
+        let mut tampered = proof.clone();
+        tampered.siblings[1][0] ^= 1;
+        assert!(!tampered.verify(&transactions[1], &root));
+
+        // the right siblings for another position
+        let mut moved = proof.clone();
+        moved.index = 0;
+        assert!(!moved.verify(&transactions[1], &root));
+        assert!(!proof.verify(&transactions[2], &root));
+        assert!(!proof.verify_id(&TxId(leaf_hash(&transactions[1])), &merkle_root(&transactions[..4])));
+    }
+}
@@ -1,51 +1,65 @@
-use sha2::{ Digest, Sha256 };
 use std::cmp::PartialEq;
+use std::collections::{ HashMap, HashSet };
 use std::ops::AddAssign;
 use std::ops::Index;
 use std::time::Instant;
-use std::time::SystemTime;
 use transaction::*;
+use hash::{ BlockHash, TxId };
+use htlc::*;
+use mempool::*;
+use header::*;
+use merkle::{ merkle_proof, merkle_root };
+use spv::TransactionProof;
+use index::*;
+use events::*;
+use std::sync::mpsc::Receiver;
+use std::sync::{ Arc, Mutex };
+use consensus::{ ConsensusEngine, ForkChoice };
+use pow::ProofOfWork;
+use stake::StakeLedger;
+use finality::{ CheckpointVote, FinalityGadget };
+use genesis::GenesisParams;
+use template::BlockTemplate;
+use stats::MiningStats;
 use crate::wallet::{ Transaction as WalletTransaction, Wallet };
 
 pub mod transaction;
+pub mod hash;
+pub mod htlc;
+pub mod mempool;
+pub mod header;
+pub mod merkle;
+pub mod spv;
+pub mod index;
+pub mod search;
+pub mod events;
+pub mod consensus;
+pub mod pow;
+pub mod poa;
+pub mod stake;
+pub mod pos;
+pub mod finality;
+pub mod timestamp;
+pub mod genesis;
+pub mod template;
+pub mod stats;
+
+pub use search::{ BlockSearch, BlockSearchResult };
 
 pub trait Serialization<T> {
     fn serialization(&self) -> Vec<u8>;
     fn deserialization(bytes: Vec<u8>) -> T;
 }
 
-pub enum BlockSearch {
-    // tag value
-    SearchByIndex(usize),
-    SearchByPreviousHash(Vec<u8>),
-    SearchByBlockHash(Vec<u8>),
-    SearchByNonce(i32),
-    SearchByTimestamp(u128),
-    SearchByTransaction(Vec<u8>),
-}
-
-pub enum BlockSearchResult<'a> {
-    Success(&'a Block),
-    FailOfEmptyBlocks,
-    FailOfIndex(usize),
-    FailOfPreviousHash(Vec<u8>),
-    FailOfBlockHash(Vec<u8>),
-    FailOfNonce(i32),
-    FailOfTimeStamp(u128),
-    FailOfTransaction(Vec<u8>),
-}
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Block {
-    pub nonce: i32,
-    pub previous_hash: Vec<u8>,
-    pub time_stamp: u128,
+    pub header: BlockHeader,
     pub transactions: Vec<Vec<u8>>,
 }
 
 impl AddAssign<i32> for Block {
     fn add_assign(&mut self, rhs: i32) {
-        self.nonce += rhs;
+        self.header.nonce += rhs;
     }
 }
 
@@ -62,9 +76,7 @@ impl Index<usize> for BlockChain {
     fn index(&self, idx: usize) -> &Self::Output {
         let res = self.chain.get(idx);
         match res {
-            Some(block) => {
-                return block;
-            }
+            Some(block) => block,
             None => {
                 panic!("index out of range for the chain");
             }
@@ -72,31 +84,61 @@ impl Index<usize> for BlockChain {
     }
 }
 
+// the height and hash of the last block, which together identify the tip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainTip {
+    pub height: u64,
+    pub hash: BlockHash,
+}
+
 #[derive(Debug)]
 pub struct BlockChain {
-    transaction_pool: Vec<Vec<u8>>,
+    mempool: Mempool,
+    // a block's height is its position in the chain
     chain: Vec<Block>,
+    // block hash to height, kept in step with the chain
+    hash_index: HashMap<BlockHash, u64>,
+    tx_index: TxIndex,
+    // balances, sequences, htlcs and stakes as of the tip
+    state_index: StateIndex,
+    events: EventBus,
+    consensus: Box<dyn ConsensusEngine>,
+    // checkpoint votes and the last final block, None without a finality gadget
+    finality: Option<FinalityGadget>,
+    // what create_block and any miner given the handle have mined so far,
+    // shared so hashing threads record without taking the chain lock
+    mining_stats: Arc<Mutex<MiningStats>>,
     //the address for the miner
     blockchain_address: String,
 }
 
 impl Block {
-    pub fn new(nonce: i32, previous_hash: Vec<u8>) -> Self {
+    pub fn new(height: u64, nonce: i32, previous_hash: BlockHash) -> Self {
         // this method will take control of the input of the previous_hash
-        let time_now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
         Block {
-            nonce: nonce,
-            previous_hash: previous_hash,
-            time_stamp: time_now.as_nanos(),
+            header: BlockHeader {
+                version: BlockHeader::VERSION,
+                height,
+                previous_hash,
+                merkle_root: merkle_root(&[]),
+                time_stamp: timestamp::now(),
+                difficulty: 0,
+                nonce,
+                extra: Vec::new(),
+                seal: Vec::new(),
+            },
             transactions: Vec::<Vec<u8>>::new(), // Initializes an empty list of transactions,
             // each transaction will be stored as a Vec<u8> (e.g., serialized data)
         }
     }
 
+    pub fn update_merkle_root(&mut self) {
+        // has to be called whenever the transactions change
+        self.header.merkle_root = merkle_root(&self.transactions);
+    }
+
     pub fn print(&self) {
-        println!("timestamp: {:x}", self.time_stamp);
-        println!("nonce: {}", self.nonce);
-        println!("previous_hash: {:?}", self.previous_hash);
+        self.header.print();
         println!("transactions: {:?}", self.transactions);
         for (idx, tx) in self.transactions.iter().enumerate() {
             let transaction = Transaction::deserialization(tx.to_vec());
@@ -106,156 +148,705 @@ impl Block {
                 transaction.sender_address,
                 String::from_utf8_lossy(&transaction.sender_address)
             );
-            println!(
-                "  To (bytes): {:?}  => '{}'",
-                transaction.recipient_address,
-                String::from_utf8_lossy(&transaction.recipient_address)
-            );
-            println!("  Value: {}", transaction.value);
+            for (out_idx, output) in transaction.outputs.iter().enumerate() {
+                println!("  Output {}:", out_idx);
+                println!(
+                    "    To (bytes): {:?}  => '{}'",
+                    output.recipient_address,
+                    String::from_utf8_lossy(&output.recipient_address)
+                );
+                println!("    Value: {}", output.value);
+            }
+            println!("  Total: {}", transaction.total_value());
+            if let Some(lock_time) = transaction.lock_time {
+                println!("  Lock time: {:?}", lock_time);
+            }
+            if transaction.kind != TransactionKind::Transfer {
+                println!("  Kind: {:?}", transaction.kind);
+            }
         }
     }
 
-    pub fn hash(&self) -> Vec<u8> {
-        let mut bin = Vec::<u8>::new();
-
-        // add all these to bin
-        bin.extend(self.nonce.to_be_bytes());
-        bin.extend(self.previous_hash.clone());
-        bin.extend(self.time_stamp.to_be_bytes());
-        for tx in self.transactions.iter() {
-            bin.extend(tx.clone());
-        }
+    pub fn hash(&self) -> BlockHash {
+        // the transactions are covered through the merkle root
+        self.header.hash()
+    }
 
-        let mut hasher = Sha256::new();
-        hasher.update(bin);
-        // return the result to vec
-        hasher.finalize().to_vec()
+    pub fn height(&self) -> u64 {
+        self.header.height
     }
 }
 
 impl BlockChain {
-    const DIFFICULTY: usize = 5;
-    const MINING_SENDER: &str = "THE BLOCKCHAIN";
+    const DIFFICULTY: u32 = 5;
+    pub const MINING_SENDER: &str = "THE BLOCKCHAIN";
     const MINING_REWARD: u64 = 1;
+    const HASHRATE_WINDOW: usize = 20;
+    // upper bound on the serialized size of a block's transactions, coinbase included
+    pub const MAX_BLOCK_BYTES: usize = 250_000;
 
     pub fn new(address: String) -> Self {
+        BlockChain::with_mempool_config(address, MempoolConfig::default())
+    }
+
+    pub fn with_mempool_config(address: String, config: MempoolConfig) -> Self {
+        BlockChain::with_consensus(address, config, Box::new(ProofOfWork::new(BlockChain::DIFFICULTY)))
+    }
+
+    pub fn with_consensus(address: String, config: MempoolConfig, consensus: Box<dyn ConsensusEngine>) -> Self {
+        BlockChain::with_genesis(address, config, consensus, GenesisParams::default())
+    }
+
+    pub fn with_genesis(
+        address: String,
+        config: MempoolConfig,
+        consensus: Box<dyn ConsensusEngine>,
+        params: GenesisParams
+    ) -> Self {
         let mut bc = BlockChain {
-            transaction_pool: Vec::<Vec<u8>>::new(),
+            mempool: Mempool::new(config),
             chain: Vec::<Block>::new(),
+            hash_index: HashMap::<BlockHash, u64>::new(),
+            tx_index: TxIndex::new(),
+            state_index: StateIndex::new(StakeLedger::with_genesis(&consensus.genesis_stake())),
+            events: EventBus::new(),
+            consensus,
+            finality: None,
+            mining_stats: Arc::new(Mutex::new(MiningStats::new())),
             blockchain_address: address,
         };
 
-        let b = Block::new(0, vec![0 as u8; 32]);
-
-        bc.chain.push(b);
+        bc.push_block(params.block());
         bc.mining();
         bc
     }
 
-    pub fn create_block(&mut self, nonce: i32, previous_hash: Vec<u8>) {
-        let mut b = Block::new(nonce, previous_hash);
-        for tx in self.transaction_pool.iter() {
-            b.transactions.push(tx.clone());
-        }
-        self.transaction_pool.clear();
+    pub fn with_finality(mut self, gadget: FinalityGadget) -> Self {
+        self.finality = Some(gadget);
+        self.update_finality();
+        self
+    }
+
+    pub fn create_block(&mut self, nonce: i32, previous_hash: BlockHash) -> bool {
+        let mut b = self.assemble_block(&self.blockchain_address);
+        b.header.nonce = nonce;
+        b.header.previous_hash = previous_hash;
         let now = Instant::now();
-        let proof_hash = BlockChain::do_proof_of_work(&mut b);
+        let hashes = match self.consensus.seal(&self.chain, &mut b) {
+            Some(attempts) => attempts,
+            None => {
+                println!("{} could not seal the block", self.consensus.name());
+                return false;
+            }
+        };
         let elapsed = now.elapsed();
-        println!("compute time: {:?}\nproof for the current block is {:?}", elapsed, proof_hash);
-        self.chain.push(b);
+        println!("compute time: {:?}\nproof for the current block is {:?}", elapsed, b.hash().to_string());
+        if self.consensus.pow_target(&self.chain).is_some() {
+            // one hash per header the seal tried
+            let mut stats = self.mining_stats.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            stats.record_hashes(0, hashes, elapsed);
+            stats.record_block(elapsed);
+        }
+        if !self.append_block(b) {
+            println!("mined block failed validation");
+            return false;
+        }
+
+        true
     }
 
-    pub fn print(&self) {
-        //  using iterator to loop over vector
-        for (i, block) in self.chain.iter().enumerate() {
-            println!("{} Block {} {}", "=".repeat(25), i, "=".repeat(25));
-            block.print();
-            println!("{}", "*".repeat(59));
+    pub fn get_block_template(&self, payout_address: &str) -> BlockTemplate {
+        // the block create_block would seal, for a miner outside the node
+        BlockTemplate {
+            block: self.assemble_block(payout_address),
+            min_time_stamp: timestamp::median_time_past(&self.chain) + 1,
+            algorithm: self.genesis_params().pow_algorithm,
         }
     }
 
-    pub fn last_block(&self) -> &Block {
-        if self.chain.len() > 1 {
-            return &self.chain[self.chain.len() - 1];
+    pub fn submit_block(&mut self, block: Block) -> bool {
+        // a solved template, only useful while it still builds on the tip
+        if block.header.previous_hash != self.tip().hash {
+            println!("submitted block is stale");
+            return false;
         }
-
-        &self.chain[0]
+        if !self.append_block(block) {
+            println!("submitted block failed validation");
+            return false;
+        }
+        true
     }
 
-    pub fn search_block(&self, search: BlockSearch) -> BlockSearchResult {
-        for (idx, block) in self.chain.iter().enumerate() {
-            match search {
-                BlockSearch::SearchByIndex(index) => {
-                    if idx == index {
-                        return BlockSearchResult::Success(block);
-                    }
+    fn assemble_block(&self, payout_address: &str) -> Block {
+        // the next block with its coinbase paying payout_address, complete except for the seal
+        let height = self.height() + 1;
+        let mut b = Block::new(height, 0, self.tip().hash);
+        b.header.time_stamp = timestamp::next_time_stamp(&self.chain, b.header.time_stamp);
+        b.header.difficulty = self.consensus.next_difficulty(&self.chain);
+        /*
+        pick the best paying package (a pending transaction plus the ancestors it
+        spends from) again and again, ancestors always go in before descendants
+        so a child with a high fee pulls its cheap parents into the block
+        */
+        let mut htlcs = self.htlcs();
+        let mut stakes = self.stakes();
+        let mut included = HashSet::<TxId>::new();
+        let mut rejected = HashSet::<TxId>::new();
+        let mut selected = Vec::<Transaction>::new();
+        // the coinbase has the same size whatever fees it collects
+        let mut block_bytes = BlockChain::coinbase(payout_address, height, 0).serialization().len();
+        loop {
+            let mut best: Option<MempoolPackage> = None;
+            for entry in self.mempool.entries() {
+                if included.contains(&entry.id) || rejected.contains(&entry.id) {
+                    continue;
+                }
 
-                    if idx >= self.chain.len() {
-                        return BlockSearchResult::FailOfIndex(idx);
+                let package = match self.mempool.package(&entry.id, &included) {
+                    Some(package) => package,
+                    None => {
+                        continue;
                     }
+                };
+                if block_bytes + package.size > BlockChain::MAX_BLOCK_BYTES {
+                    continue;
                 }
 
-                BlockSearch::SearchByPreviousHash(ref hash) => {
-                    /*
-                       enum matching can cause data ownership transfer, the hash value
-                       attach to search is transfer to the local variable of hash here,
-                       when the block is executed the value of hash is dropped, then in
-                       next round we will not have any value to get
-                    */
-                    if block.previous_hash == *hash {
-                        return BlockSearchResult::Success(block);
-                    }
+                // time locked transactions stay in the pool until they are eligible,
+                // and hold back everything that depends on them
+                if
+                    package.entries
+                        .iter()
+                        .any(|member| rejected.contains(&member.id) || !member.transaction.is_final(height, b.header.time_stamp))
+                {
+                    continue;
+                }
 
-                    if idx >= self.chain.len() {
-                        return BlockSearchResult::FailOfPreviousHash(hash.to_vec());
-                    }
+                if best.as_ref().is_none_or(|best| package.has_higher_fee_rate(best)) {
+                    best = Some(package);
                 }
+            }
 
-                BlockSearch::SearchByBlockHash(ref hash) => {
-                    if block.hash() == *hash {
-                        return BlockSearchResult::Success(block);
-                    }
+            let package = match best {
+                Some(package) => package,
+                None => {
+                    break;
+                }
+            };
 
-                    if idx >= self.chain.len() {
-                        return BlockSearchResult::FailOfPreviousHash(hash.to_vec());
-                    }
+            // htlc settlements and stake changes that are no longer valid are left out
+            let mut package_htlcs = htlcs.clone();
+            let mut package_stakes = stakes.clone();
+            if
+                package.entries.iter().all(|member| {
+                    apply_htlc(&mut package_htlcs, &member.transaction, height) &&
+                        package_stakes.apply(&member.transaction)
+                })
+            {
+                htlcs = package_htlcs;
+                stakes = package_stakes;
+                block_bytes += package.size;
+                for member in package.entries {
+                    included.insert(member.id);
+                    selected.push(member.transaction.clone());
                 }
+            } else if let Some(root) = package.entries.last() {
+                rejected.insert(root.id);
+            }
+        }
 
-                BlockSearch::SearchByNonce(nonce) => {
-                    if block.nonce == nonce {
-                        return BlockSearchResult::Success(block);
-                    }
+        let fees = selected
+            .iter()
+            .map(|tx| tx.fee)
+            .sum();
+        b.transactions.push(BlockChain::coinbase(payout_address, height, fees).serialization());
+        for tx in selected.iter() {
+            b.transactions.push(tx.serialization());
+        }
+        b.update_merkle_root();
+        b
+    }
 
-                    if idx >= self.chain.len() {
-                        return BlockSearchResult::FailOfNonce(nonce);
-                    }
+    pub fn append_block(&mut self, block: Block) -> bool {
+        // puts a sealed block on top of the chain, its transactions leave the pool
+        if !self.verify_block(&block) {
+            return false;
+        }
+
+        let confirmed: Vec<TxId> = block.transactions
+            .iter()
+            .map(|tx| Transaction::deserialization(tx.clone()).id())
+            .collect();
+        self.push_block(block);
+        for id in confirmed.iter() {
+            self.mempool.remove(id);
+        }
+        self.revalidate_mempool();
+        // votes may already be waiting for the checkpoint this block completes
+        self.update_finality();
+        true
+    }
+
+    pub fn reorganize(&mut self, fork: Vec<Block>) -> bool {
+        /*
+        fork is a branch of blocks whose first block builds on a block of this
+        chain, the consensus engine decides whether the chain switches to it.
+        the blocks come from peers, so each one goes through verify_block like
+        any other block, and a branch with an invalid block, a forged transfer
+        or an overspend leaves the chain as it was
+        */
+        let fork_height = match fork.first().and_then(|block| self.height_of(&block.header.previous_hash)) {
+            Some(height) => height,
+            None => {
+                println!("fork does not build on this chain");
+                return false;
+            }
+        };
+        if self.is_final(fork_height + 1) {
+            println!("fork reverts a finalized checkpoint");
+            return false;
+        }
+
+        let candidate: Vec<Block> = self.chain[..=fork_height as usize]
+            .iter()
+            .chain(fork.iter())
+            .cloned()
+            .collect();
+        // fork choice goes by what the headers claim, a claim is only worth
+        // something once the seal backs it, so check them before reverting anything
+        for (offset, block) in fork.iter().enumerate() {
+            let ancestors = &candidate[..=(fork_height as usize) + offset];
+            let parent = ancestors[ancestors.len() - 1].header.hash();
+            if block.header.previous_hash != parent || block.header.height != (ancestors.len() as u64) {
+                println!("fork blocks do not link up");
+                return false;
+            }
+            if !self.consensus.verify_seal(ancestors, &block.header) {
+                println!("fork block {} has an invalid seal", block.height());
+                return false;
+            }
+        }
+        if self.consensus.select_fork(&self.chain, &candidate) == ForkChoice::Keep {
+            println!("keeping the current chain");
+            return false;
+        }
+
+        let mut reverted = Vec::<Block>::new();
+        while self.height() > fork_height {
+            match self.revert_block() {
+                Some(block) => reverted.push(block),
+                None => {
+                    break;
                 }
+            }
+        }
 
-                BlockSearch::SearchByTimestamp(time_stamp) => {
-                    if block.time_stamp == time_stamp {
-                        return BlockSearchResult::Success(block);
+        for block in fork {
+            if !self.append_block(block) {
+                println!("fork has an invalid block, restoring the previous chain");
+                while self.height() > fork_height {
+                    self.revert_block();
+                }
+                for block in reverted.into_iter().rev() {
+                    let height = block.height();
+                    if !self.append_block(block) {
+                        println!("could not restore block {}, the chain stays at height {}", height, self.height());
+                        break;
                     }
+                }
+                return false;
+            }
+        }
 
-                    if idx >= self.chain.len() {
-                        return BlockSearchResult::FailOfTimeStamp(time_stamp);
-                    }
+        true
+    }
+
+    pub fn consensus(&self) -> &dyn ConsensusEngine {
+        self.consensus.as_ref()
+    }
+
+    pub fn miner_address(&self) -> &str {
+        // where blocks mined by create_block pay out
+        &self.blockchain_address
+    }
+
+    pub fn mining_stats(&self) -> MiningStats {
+        self.mining_stats.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    pub fn mining_stats_handle(&self) -> Arc<Mutex<MiningStats>> {
+        // for miners outside the chain to record into the same stats
+        Arc::clone(&self.mining_stats)
+    }
+
+    pub fn estimated_network_hashrate(&self) -> Option<f64> {
+        // from the difficulty and timestamps of the recent blocks, proof of work only
+        self.consensus.pow_target(&self.chain)?;
+        ProofOfWork::network_hashrate(&self.chain, BlockChain::HASHRATE_WINDOW)
+    }
+
+    pub fn genesis_hash(&self) -> BlockHash {
+        // what wallets sign transactions for this chain with
+        self.chain[0].hash()
+    }
+
+    pub fn genesis_params(&self) -> GenesisParams {
+        GenesisParams::from_chain(&self.chain)
+    }
+
+    pub fn finality(&self) -> Option<&FinalityGadget> {
+        self.finality.as_ref()
+    }
+
+    pub fn finalized(&self) -> Option<ChainTip> {
+        self.finality.as_ref().and_then(|gadget| gadget.finalized())
+    }
+
+    pub fn is_final(&self, height: u64) -> bool {
+        self.finality.as_ref().is_some_and(|gadget| gadget.is_final(height))
+    }
+
+    pub fn submit_checkpoint_vote(&mut self, vote: &CheckpointVote) -> bool {
+        let gadget = match self.finality.as_mut() {
+            Some(gadget) => gadget,
+            None => {
+                println!("this chain has no finality gadget");
+                return false;
+            }
+        };
+        if !gadget.submit_vote(vote) {
+            return false;
+        }
+        self.update_finality();
+        true
+    }
+
+    fn update_finality(&mut self) {
+        let checkpoint = match self.finality.as_mut() {
+            Some(gadget) => gadget.finalize(&self.chain),
+            None => None,
+        };
+        if let Some(checkpoint) = checkpoint {
+            self.events.publish(ChainEvent::CheckpointFinalized(checkpoint));
+        }
+    }
+
+    fn push_block(&mut self, block: Block) {
+        // every block goes on the chain through here so the index stays in step
+        self.hash_index.insert(block.hash(), block.height());
+        self.tx_index.add_block(&block);
+        self.state_index.add_block(&block);
+        self.events.publish(ChainEvent::BlockAppended(BlockEvent::new(&block)));
+        self.chain.push(block);
+    }
+
+    pub fn revert_block(&mut self) -> Option<Block> {
+        // takes the tip block off the chain and puts its transactions back in the pool
+        if self.chain.len() <= 1 {
+            println!("cannot revert the genesis block");
+            return None;
+        }
+        if self.is_final(self.height()) {
+            println!("cannot revert a finalized block");
+            return None;
+        }
+
+        let block = self.chain.pop()?;
+        self.hash_index.remove(&block.hash());
+        self.tx_index.remove_block(&block);
+        self.state_index.remove_block(&block);
+        self.events.publish(ChainEvent::BlockReverted(BlockEvent::new(&block)));
+
+        // the reverted transactions arrived before anything still pending
+        let pending = self.mempool.drain();
+        for tx in block.transactions.iter().skip(1) {
+            let transaction = Transaction::deserialization(tx.clone());
+            let accepted = match self.check_pending_transaction(&transaction, None) {
+                Some(parents) => self.mempool.insert(transaction.clone(), parents),
+                None => false,
+            };
+            if !accepted {
+                println!("dropped reverted transaction {}", transaction.id());
+                self.events.publish(ChainEvent::TransactionDropped {
+                    id: transaction.id(),
+                    transaction,
+                    reason: DropReason::Invalid,
+                });
+            }
+        }
+        self.mempool.restore(pending);
+        self.revalidate_mempool();
+        Some(block)
+    }
+
+    pub fn subscribe(&mut self, filter: EventFilter) -> Receiver<ChainEvent> {
+        self.events.subscribe(filter)
+    }
+
+    fn publish_dropped(&mut self) {
+        for (entry, reason) in self.mempool.take_dropped() {
+            self.events.publish(ChainEvent::TransactionDropped {
+                id: entry.id,
+                transaction: entry.transaction,
+                reason,
+            });
+        }
+    }
+
+    fn coinbase(payout_address: &str, height: u64, fees: u64) -> Transaction {
+        /*
+        if a block is mined, a transaction will created and the chain will send
+        a coin plus the fees of the block to the miner
+        */
+        let mut tx = Transaction::new(
+            BlockChain::MINING_SENDER.as_bytes().to_vec(),
+            payout_address.as_bytes().to_vec(),
+            BlockChain::MINING_REWARD + fees
+        );
+        tx.kind = TransactionKind::Coinbase { height };
+        tx
+    }
+
+    pub fn verify_block(&self, block: &Block) -> bool {
+        // checks a block as the next block on top of the current chain
+        let tip = self.tip();
+        if block.header.previous_hash != tip.hash {
+            println!("block does not extend the last block");
+            return false;
+        }
+
+        if block.header.height != tip.height + 1 {
+            println!("block height does not follow the last block");
+            return false;
+        }
+
+        if block.transactions.iter().map(|tx| tx.len()).sum::<usize>() > BlockChain::MAX_BLOCK_BYTES {
+            println!("block is larger than {} bytes", BlockChain::MAX_BLOCK_BYTES);
+            return false;
+        }
+
+        if block.header.merkle_root != merkle_root(&block.transactions) {
+            println!("block merkle root does not match its transactions");
+            return false;
+        }
+
+        if !timestamp::check_time_stamp(timestamp::median_time_past(&self.chain), &block.header, timestamp::now()) {
+            return false;
+        }
+
+        if !self.consensus.verify_seal(&self.chain, &block.header) {
+            println!("block seal is not valid for {}", self.consensus.name());
+            return false;
+        }
+
+        let height = block.header.height;
+        let mut htlcs = self.htlcs();
+        let mut stakes = self.stakes();
+        let mut fees = 0u64;
+        let mut reward = 0u64;
+        let mut sequences = HashSet::<(Vec<u8>, u64)>::new();
+        // what the block's transactions add to and take from each balance so far,
+        // on top of the confirmed balances of the senders
+        let mut confirmed = HashMap::<Vec<u8>, i128>::new();
+        let mut changes = HashMap::<Vec<u8>, i128>::new();
+        for (idx, tx) in block.transactions.iter().enumerate() {
+            let transaction = match Transaction::try_deserialization(tx) {
+                Some(transaction) => transaction,
+                None => {
+                    println!("block includes a malformed transaction");
+                    return false;
                 }
+            };
+            // exactly one coinbase, at the start of the block and for its height
+            let is_coinbase = transaction.sender_address == BlockChain::MINING_SENDER.as_bytes();
+            if (idx == 0) != is_coinbase || is_coinbase != matches!(transaction.kind, TransactionKind::Coinbase { .. }) {
+                println!("block coinbase is misplaced");
+                return false;
+            }
+            if is_coinbase && transaction.kind != (TransactionKind::Coinbase { height: block.header.height }) {
+                println!("block coinbase is for another height");
+                return false;
+            }
 
-                BlockSearch::SearchByTransaction(ref transaction) => {
-                    for tx in block.transactions.iter() {
-                        if tx == transaction {
-                            return BlockSearchResult::Success(block);
-                        }
+            if transaction.checked_spent_value().is_none() {
+                println!("block includes a transaction whose value overflows");
+                return false;
+            }
 
-                        if idx >= self.chain.len() {
-                            return BlockSearchResult::FailOfTransaction(transaction.to_vec());
-                        }
-                    }
+            if idx == 0 {
+                reward = transaction.total_value();
+            } else {
+                fees = fees.saturating_add(transaction.fee);
+
+                if
+                    self.state_index.has_sequence(&transaction.sender_address, transaction.sequence) ||
+                    !sequences.insert((transaction.sender_address.clone(), transaction.sequence))
+                {
+                    println!("block reuses a sender sequence");
+                    return false;
+                }
+
+                // blocks come from miners and peers, only the sender's signature
+                // makes a transaction spend from its balance
+                if !transaction.verify_authorization(&self.genesis_hash()) {
+                    println!("block includes a transaction the sender did not sign");
+                    return false;
                 }
+
+                let sender = &transaction.sender_address;
+                let balance = *confirmed
+                    .entry(sender.clone())
+                    .or_insert_with(|| self.calculate_total_amt(String::from_utf8_lossy(sender).to_string()) as i128);
+                let change = changes.entry(sender.clone()).or_default();
+                *change -= transaction.spent_value() as i128;
+                if balance + *change < 0 {
+                    println!("block includes a transaction its sender cannot afford");
+                    return false;
+                }
+            }
+
+            if !transaction.is_final(height, block.header.time_stamp) {
+                println!("block includes a transaction before its lock time");
+                return false;
+            }
+
+            if !apply_htlc(&mut htlcs, &transaction, height) {
+                println!("block includes an invalid htlc settlement");
+                return false;
+            }
+
+            let unstaked = stakes.released(&transaction);
+            if !stakes.apply(&transaction) {
+                println!("block includes an invalid stake transaction");
+                return false;
             }
+
+            // later transactions in the block may spend what this one pays out
+            for output in transaction.outputs.iter() {
+                *changes.entry(output.recipient_address.clone()).or_default() += output.value as i128;
+            }
+            let released = match &transaction.kind {
+                | TransactionKind::HtlcClaim { htlc_id, .. }
+                | TransactionKind::HtlcRefund { htlc_id } => htlcs.get(htlc_id).map_or(0, |htlc| htlc.lock.value),
+                TransactionKind::Unstake { .. } => unstaked,
+                _ => 0,
+            };
+            *changes.entry(transaction.sender_address.clone()).or_default() += released as i128;
+        }
+
+        if reward > BlockChain::MINING_REWARD.saturating_add(fees) {
+            println!("block coinbase pays more than the reward and fees");
+            return false;
+        }
+
+        true
+    }
+
+    pub fn print(&self) {
+        //  using iterator to loop over vector
+        for (i, block) in self.chain.iter().enumerate() {
+            println!("{} Block {} {}", "=".repeat(25), i, "=".repeat(25));
+            block.print();
+            println!("{}", "*".repeat(59));
+        }
+    }
+
+    pub fn last_block(&self) -> &Block {
+        // the chain always holds at least the genesis block
+        &self.chain[self.chain.len() - 1]
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.chain
+    }
+
+    pub fn tip(&self) -> ChainTip {
+        let block = self.last_block();
+        ChainTip {
+            height: block.height(),
+            hash: block.hash(),
         }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.last_block().height()
+    }
+
+    pub fn block_at(&self, height: u64) -> Option<&Block> {
+        self.chain.get(usize::try_from(height).ok()?)
+    }
+
+    pub fn block_by_hash(&self, hash: &BlockHash) -> Option<&Block> {
+        self.block_at(self.height_of(hash)?)
+    }
+
+    pub fn height_of(&self, hash: &BlockHash) -> Option<u64> {
+        self.hash_index.get(hash).copied()
+    }
+
+    pub fn header(&self, height: u64) -> Option<&BlockHeader> {
+        self.block_at(height).map(|block| &block.header)
+    }
 
-        return BlockSearchResult::FailOfEmptyBlocks;
+    pub fn headers(&self, from_height: u64, count: usize) -> Vec<&BlockHeader> {
+        // a run of headers starting at from_height, without the block bodies
+        self.chain
+            .iter()
+            .skip(from_height as usize)
+            .take(count)
+            .map(|block| &block.header)
+            .collect()
+    }
+
+    pub fn transaction_proof(&self, id: &TxId) -> Option<TransactionProof> {
+        // merkle proof that a confirmed transaction is in its block, for light clients
+        let location = self.tx_index.location(id)?;
+        let block = self.block_at(location.height)?;
+        Some(TransactionProof {
+            height: location.height,
+            transaction: block.transactions.get(location.position)?.clone(),
+            proof: merkle_proof(&block.transactions, location.position)?,
+        })
+    }
+
+    pub fn receipt(&self, id: &TxId) -> Option<TransactionReceipt> {
+        let location = self.tx_index.location(id)?;
+        let block = self.block_at(location.height)?;
+        let tx = block.transactions.get(location.position)?;
+        Some(TransactionReceipt {
+            id: *id,
+            height: location.height,
+            position: location.position,
+            block_hash: block.hash(),
+            time_stamp: block.header.time_stamp,
+            confirmations: self.height() - location.height + 1,
+            transaction: Transaction::deserialization(tx.clone()),
+        })
+    }
+
+    pub fn address_history(&self, address: &str, skip: usize, limit: usize) -> Vec<TransactionReceipt> {
+        // a page of the confirmed transactions sending from or paying the address, newest first
+        self.tx_index
+            .history(address.as_bytes(), skip, limit)
+            .iter()
+            .filter_map(|id| self.receipt(id))
+            .collect()
+    }
+
+    pub fn address_history_len(&self, address: &str) -> usize {
+        self.tx_index.history_len(address.as_bytes())
+    }
+
+    pub fn header_chain(&self) -> HeaderChain {
+        // every block went through verify_block on its way in
+        HeaderChain::trusted(
+            self.chain[0].header.clone(),
+            self.chain
+                .iter()
+                .skip(1)
+                .map(|block| block.header.clone())
+                .collect()
+        )
     }
 
     pub fn add_transaction(&mut self, tx: &WalletTransaction) -> bool {
@@ -264,93 +855,535 @@ impl BlockChain {
             return false;
         }
 
-        if tx.sender != BlockChain::MINING_SENDER && !Wallet::verify_transaction(tx) {
+        // the mining reward is created by the chain itself, never submitted
+        if tx.sender == BlockChain::MINING_SENDER {
+            println!("transaction cannot be sent from the mining sender");
+            return false;
+        }
+
+        if !Wallet::verify_transaction(tx) {
             println!("invalid transaction");
             return false;
         }
 
-        if
-            tx.sender != BlockChain::MINING_SENDER &&
-            self.calculate_total_amt(tx.sender.clone()) < (tx.amount as i64)
-        {
-            println!("sender dose not have enough balance");
+        // a signature for another chain must not spend on this one
+        if tx.genesis_hash != self.genesis_hash().to_string() {
+            println!("transaction is signed for another chain");
             return false;
         }
 
-        let transaction = Transaction::new(
-            tx.sender.as_bytes().to_vec(),
-            tx.recipient.as_bytes().to_vec(),
-            tx.amount
-        );
+        // the whole batch is accepted or rejected against the sender's balance at once
+        if !matches!(tx.total_amount(), Some(total) if total <= (i64::MAX as u64)) {
+            println!("transaction amount overflow");
+            return false;
+        }
 
-        for tx_in_pool in self.transaction_pool.iter() {
-            if *tx_in_pool == transaction.serialization() {
-                break;
+        let transaction = match Transaction::from_wallet(tx) {
+            Some(transaction) => transaction,
+            None => {
+                println!("malformed transaction kind");
+                return false;
             }
-        }
+        };
 
-        self.transaction_pool.push(transaction.serialization());
-        true
-    }
+        // expiry only starts once a locked transaction can be mined
+        self.mark_eligible();
 
-    fn do_proof_of_work(block: &mut Block) -> String {
-        loop {
-            let hash = block.hash();
-            let hash_str = hex::encode(&hash);
-            // Check if the hash string starts with the required number of leading zeroes (i.e., difficulty target)
-            if hash_str[0..BlockChain::DIFFICULTY] == "0".repeat(BlockChain::DIFFICULTY) {
-                // If the hash meets the difficulty criteria, return it as a valid proof-of-work
-                return hash_str;
+        // a pending transaction with the same sequence gets replaced by this one
+        let replacing = self.mempool
+            .find_sequence(&transaction.sender_address, transaction.sequence)
+            .map(|entry| entry.transaction.clone());
+
+        let parents = match self.check_pending_transaction(&transaction, replacing.as_ref()) {
+            Some(parents) => parents,
+            None => {
+                return false;
             }
-            // If not valid, increment the block's nonce to try again
-            *block += 1;
+        };
+
+        let id = transaction.id();
+        let accepted = match replacing {
+            Some(_) => self.mempool.replace(transaction.clone(), parents),
+            None => self.mempool.insert(transaction.clone(), parents),
+        };
+        self.publish_dropped();
+        if accepted {
+            self.events.publish(ChainEvent::TransactionAccepted { id, transaction });
         }
+        accepted
     }
 
-    pub fn mining(&mut self) -> bool {
+    fn check_pending_transaction(
+        &self,
+        transaction: &Transaction,
+        replacing: Option<&Transaction>
+    ) -> Option<Vec<TxId>> {
         /*
-        if a block is mined, a transaction will created and the chain will send
-        a coin to the miner
+        checks a transaction against the chain state and the rest of the mempool,
+        leaving out the pending transaction it replaces. returns the ids of the
+        pending transactions whose outputs it needs to cover its spending
         */
-        let tx = WalletTransaction {
-            sender: BlockChain::MINING_SENDER.clone().to_string(),
-            recipient: self.blockchain_address.clone(),
-            amount: BlockChain::MINING_REWARD,
-            signature: "".to_string(),
-            public_key: "".to_string(),
-        };
-        self.add_transaction(&tx);
+        if self.state_index.has_sequence(&transaction.sender_address, transaction.sequence) {
+            println!("sequence {} has already been used by the sender", transaction.sequence);
+            return None;
+        }
 
-        self.create_block(0, self.last_block().hash());
+        // only plain transfers pay outputs, htlc transactions move the locked value
+        if (transaction.kind == TransactionKind::Transfer) == transaction.outputs.is_empty() {
+            println!("transaction outputs do not match its kind");
+            return None;
+        }
 
-        true
-    }
+        let height = self.height() + 1;
+        match &transaction.kind {
+            TransactionKind::Transfer => {}
+            TransactionKind::HtlcCreate(lock) => {
+                if lock.value == 0 || lock.value > (i64::MAX as u64) || lock.hash_lock.len() != 32 {
+                    println!("invalid htlc");
+                    return None;
+                }
 
-    pub fn calculate_total_amt(&self, address: String) -> i64 {
-        let mut total_amt: i64 = 0;
-
-        for i in 0..self.chain.len() {
-            let block = &self[i];
-            for t in block.transactions.iter() {
-                let tx = Transaction::deserialization(t.clone());
-                let value = tx.value;
-
-                /*
-                into is a trait used for converting one type into another type, String implement the trait
-                of into with many different type, therefore we need to convert String to the trait with the 
-                right type,here we want string convert itself to Vec<u8>, then we need to convert String
-                to type Into<Vec<u8>>.
-                */
-                if <String as Into<Vec<u8>>>::into(address.clone()) == tx.recipient_address {
-                    total_amt += value as i64;
+                if lock.expiry_height <= height {
+                    println!("htlc expires before it can be confirmed");
+                    return None;
+                }
+            }
+            TransactionKind::HtlcClaim { htlc_id, .. } | TransactionKind::HtlcRefund { htlc_id } => {
+                if !self.state_index.htlc(htlc_id).is_some_and(|htlc| htlc.can_settle(transaction, height)) {
+                    println!("htlc cannot be settled by this transaction");
+                    return None;
+                }
+
+                if self.pool_settles_htlc(htlc_id, replacing) {
+                    println!("htlc is already being settled by a pending transaction");
+                    return None;
+                }
+            }
+            TransactionKind::Coinbase { .. } => {
+                println!("a coinbase cannot be submitted");
+                return None;
+            }
+            TransactionKind::Stake { .. } | TransactionKind::Unstake { .. } | TransactionKind::Slash(_) => {
+                // on top of the stake changes already waiting in the pool
+                let mut stakes = self.stakes();
+                for entry in self.mempool.entries() {
+                    if Some(&entry.transaction) != replacing {
+                        stakes.apply(&entry.transaction);
+                    }
+                }
+                if !stakes.apply(transaction) {
+                    println!("invalid stake transaction");
+                    return None;
                 }
+            }
+        }
+
+        // pending spends of the same sender are already committed
+        let sender = String::from_utf8_lossy(&transaction.sender_address).to_string();
+        let replaced_spent = replacing.map_or(0, |tx| tx.spent_value());
+        let mut available =
+            (self.calculate_total_amt(sender) as i128) -
+            (self.mempool.pending_spent(&transaction.sender_address) as i128) +
+            (replaced_spent as i128);
+        let needed = transaction.spent_value() as i128;
+
+        // not enough confirmed funds, spend what pending transactions pay the sender,
+        // except for the replaced transaction and whatever depends on it
+        let mut parents = Vec::<TxId>::new();
+        if available < needed {
+            let mut excluded = HashSet::<TxId>::new();
+            if let Some(replaced) = replacing {
+                excluded.insert(replaced.id());
+                excluded.extend(self.mempool.descendants(&replaced.id()).into_iter().map(|entry| entry.id));
+            }
+
+            for (entry, value) in self.mempool.pending_received(&transaction.sender_address) {
+                if excluded.contains(&entry.id) {
+                    continue;
+                }
+
+                available += value as i128;
+                parents.push(entry.id);
+                if available >= needed {
+                    break;
+                }
+            }
+        }
 
-                if <String as Into<Vec<u8>>>::into(address.clone()) == tx.sender_address {
-                    total_amt -= value as i64;
+        if available < needed {
+            println!("sender dose not have enough balance");
+            return None;
+        }
+
+        Some(parents)
+    }
+
+    fn revalidate_mempool(&mut self) {
+        // a new block can make pending transactions invalid, replay them in
+        // arrival order against the new chain state
+        self.mark_eligible();
+        let expired = self.mempool.expire(Instant::now());
+        if expired > 0 {
+            println!("{} transactions expired from the mempool", expired);
+        }
+
+        // parents arrived before their children, so they are back in the pool
+        // by the time a child is checked and its dependencies are worked out again
+        for mut entry in self.mempool.drain() {
+            match self.check_pending_transaction(&entry.transaction, None) {
+                Some(parents) => {
+                    entry.parents = parents;
+                    self.mempool.restore(vec![entry]);
+                }
+                None => {
+                    println!("dropped transaction {} from the mempool", entry.id);
+                    self.events.publish(ChainEvent::TransactionDropped {
+                        id: entry.id,
+                        transaction: entry.transaction,
+                        reason: DropReason::Invalid,
+                    });
                 }
             }
         }
+        self.publish_dropped();
+    }
+
+    fn mark_eligible(&mut self) {
+        // lock times are judged against the block the pool would fill next
+        let time_stamp = timestamp::next_time_stamp(&self.chain, timestamp::now());
+        self.mempool.mark_eligible(self.height() + 1, time_stamp);
+    }
+
+    fn pool_settles_htlc(&self, id: &TxId, replacing: Option<&Transaction>) -> bool {
+        self.mempool.entries().any(|entry| {
+            if Some(&entry.transaction) == replacing {
+                return false;
+            }
+
+            match &entry.transaction.kind {
+                | TransactionKind::HtlcClaim { htlc_id, .. }
+                | TransactionKind::HtlcRefund { htlc_id } => htlc_id == id,
+                _ => false,
+            }
+        })
+    }
+
+    pub fn mempool(&self) -> &Mempool {
+        &self.mempool
+    }
+
+    pub fn next_sequence(&self, address: &str) -> u64 {
+        // one past the highest sequence the address has used, confirmed or pending
+        let confirmed = self.state_index.last_sequence(address.as_bytes());
+        let pending = self.mempool
+            .entries()
+            .filter(|entry| entry.transaction.sender_address == address.as_bytes())
+            .map(|entry| entry.transaction.sequence);
+        confirmed
+            .into_iter()
+            .chain(pending)
+            .max()
+            .map_or(0, |sequence| sequence + 1)
+    }
+
+    pub fn htlcs(&self) -> HashMap<TxId, Htlc> {
+        self.state_index.htlcs().clone()
+    }
+
+    pub fn stakes(&self) -> StakeLedger {
+        // stake locked by transactions on the chain, on top of whatever the
+        // engine bonded at genesis
+        self.state_index.stakes().clone()
+    }
+
+    pub fn htlc(&self, id: &TxId) -> Option<Htlc> {
+        self.state_index.htlc(id).cloned()
+    }
+
+    pub fn mining(&mut self) -> bool {
+        self.create_block(0, self.tip().hash)
+    }
+
+    pub fn calculate_total_amt(&self, address: String) -> i64 {
+        self.state_index.balance(address.as_bytes())
+    }
+
+    pub fn calculate_locked_amt(&self, address: String) -> u64 {
+        // value the address has locked in htlcs that are not settled yet
+        self.state_index
+            .htlcs()
+            .values()
+            .filter(|htlc| htlc.status == HtlcStatus::Locked && htlc.sender_address == address.as_bytes())
+            .map(|htlc| htlc.lock.value)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_chain(miner: &Wallet) -> BlockChain {
+        // constructing the chain mines block 1, which pays the miner the reward
+        BlockChain::with_consensus(miner.get_address(), MempoolConfig::default(), Box::new(ProofOfWork::new(1)))
+    }
+
+    fn unsealed_block(chain: &BlockChain, ancestors: &[Block], payout: &str, transactions: &[Transaction]) -> Block {
+        let previous = ancestors.last().unwrap();
+        let mut block = Block::new(previous.height() + 1, 0, previous.hash());
+        block.header.time_stamp = timestamp::next_time_stamp(ancestors, block.header.time_stamp);
+        block.header.difficulty = chain.consensus.next_difficulty(ancestors);
+        block.transactions.push(BlockChain::coinbase(payout, block.height(), 0).serialization());
+        block.transactions.extend(transactions.iter().map(|tx| tx.serialization()));
+        block.update_merkle_root();
+        block
+    }
+
+    fn block_on(chain: &BlockChain, ancestors: &[Block], transactions: &[Transaction]) -> Block {
+        // the coinbase pays someone else so it does not fund the transactions
+        let mut block = unsealed_block(chain, ancestors, "payout", transactions);
+        assert!(chain.consensus.seal(ancestors, &mut block).is_some());
+        block
+    }
+
+    fn block_with(chain: &BlockChain, transactions: &[Transaction]) -> Block {
+        block_on(chain, &chain.chain, transactions)
+    }
+
+    #[test]
+    fn verify_block_rejects_unsigned_transfer() {
+        let miner = Wallet::new();
+        let attacker = Wallet::new();
+        let mut chain = test_chain(&miner);
+
+        let forged = Transaction::new(miner.get_address().into_bytes(), attacker.get_address().into_bytes(), 1);
+        assert!(!chain.submit_block(block_with(&chain, &[forged])));
+        assert_eq!(chain.calculate_total_amt(miner.get_address()), 1);
+        assert_eq!(chain.calculate_total_amt(attacker.get_address()), 0);
+    }
+
+    #[test]
+    fn verify_block_rejects_overspend() {
+        let miner = Wallet::new();
+        let recipient = Wallet::new();
+        let mut chain = test_chain(&miner);
+
+        let overspend = Transaction::from_wallet(&miner.sign_transaction(&chain.genesis_hash(), &recipient.get_address(), 2)).unwrap();
+        assert!(!chain.submit_block(block_with(&chain, &[overspend])));
+
+        let spend = Transaction::from_wallet(&miner.sign_transaction(&chain.genesis_hash(), &recipient.get_address(), 1)).unwrap();
+        assert!(chain.submit_block(block_with(&chain, &[spend])));
+        assert_eq!(chain.calculate_total_amt(recipient.get_address()), 1);
+    }
+
+    #[test]
+    fn verify_block_rejects_tampered_witness() {
+        let miner = Wallet::new();
+        let attacker = Wallet::new();
+        let mut chain = test_chain(&miner);
+
+        let mut tampered = Transaction::from_wallet(&miner.sign_transaction(&chain.genesis_hash(), &attacker.get_address(), 1)).unwrap();
+        tampered.outputs[0].recipient_address = b"someone else".to_vec();
+        assert!(!chain.submit_block(block_with(&chain, &[tampered])));
+    }
+
+    #[test]
+    fn verify_block_rejects_malformed_transaction() {
+        let miner = Wallet::new();
+        let mut chain = test_chain(&miner);
+
+        let mut block = block_with(&chain, &[]);
+        block.transactions.push(vec![0xff; 3]);
+        block.update_merkle_root();
+        assert!(chain.consensus.seal(&chain.chain, &mut block).is_some());
+        assert!(!chain.submit_block(block));
+    }
+
+    #[test]
+    fn reorganize_refuses_fork_with_forged_transfer() {
+        let miner = Wallet::new();
+        let attacker = Wallet::new();
+        let mut chain = test_chain(&miner);
+        assert!(chain.submit_block(block_with(&chain, &[])));
+        let tip = chain.tip();
+
+        // two blocks on top of block 1 outweigh the one block they replace
+        let forged = Transaction::new(miner.get_address().into_bytes(), attacker.get_address().into_bytes(), 1);
+        let mut ancestors = chain.chain[..2].to_vec();
+        let first = block_on(&chain, &ancestors, &[forged]);
+        ancestors.push(first.clone());
+        let second = block_on(&chain, &ancestors, &[]);
+
+        assert!(!chain.reorganize(vec![first, second]));
+        assert_eq!(chain.tip(), tip);
+        assert_eq!(chain.calculate_total_amt(attacker.get_address()), 0);
+    }
+
+    #[test]
+    fn child_pays_for_its_parent() {
+        let miner = Wallet::new();
+        let (alice, bob, carol, dave) = (Wallet::new(), Wallet::new(), Wallet::new(), Wallet::new());
+        let mut chain = test_chain(&miner);
+        for payout in [&alice, &alice, &alice, &alice, &alice, &carol, &carol] {
+            let mut block = chain.assemble_block(&payout.get_address());
+            assert!(chain.consensus.seal(&chain.chain, &mut block).is_some());
+            assert!(chain.submit_block(block));
+        }
+
+        let genesis_hash = chain.genesis_hash();
+        let transfer = |from: &Wallet, to: &Wallet, amount: u64, fee: u64| {
+            let output = crate::wallet::TransactionOutput { recipient: to.get_address(), amount };
+            let transaction = WalletTransaction::new(crate::wallet::TransactionKind::Transfer, vec![output]);
+            from.sign(transaction.with_fee(fee).for_chain(&genesis_hash))
+        };
+        // a parent paying nothing, a child spending from it that pays for both,
+        // and an unrelated transaction in between on fee rate
+        assert!(chain.add_transaction(&transfer(&alice, &bob, 4, 0)));
+        assert!(chain.add_transaction(&transfer(&carol, &dave, 1, 1)));
+        assert!(chain.add_transaction(&transfer(&bob, &dave, 1, 3)));
+
+        let senders: Vec<Vec<u8>> = chain
+            .get_block_template("payout")
+            .transactions()
+            .into_iter()
+            .map(|tx| tx.sender_address)
+            .collect();
+        let expected: Vec<Vec<u8>> = [&alice, &bob, &carol]
+            .iter()
+            .map(|wallet| wallet.get_address().into_bytes())
+            .collect();
+        assert_eq!(senders, expected);
+    }
+
+    #[test]
+    fn unstaking_genesis_stake_creates_no_coins() {
+        // a node that only validates, the test signs the validator's blocks itself
+        let validator = Wallet::new();
+        let address = validator.get_address();
+        let engine = pos::ProofOfStake::new(vec![(validator.public_key_str(), 100)]).unwrap();
+        let mut chain = BlockChain::with_consensus("payout".to_string(), MempoolConfig::default(), Box::new(engine));
+        let signed_block = |chain: &BlockChain, payout: &str, transactions: &[Transaction]| {
+            let mut block = unsealed_block(chain, &chain.chain, payout, transactions);
+            consensus::sign_header(&validator, &mut block.header);
+            block
+        };
+        let genesis_hash = chain.genesis_hash();
+        let signed = |kind: crate::wallet::TransactionKind, outputs: Vec<crate::wallet::TransactionOutput>, sequence: u64| {
+            let transaction = WalletTransaction::new(kind, outputs).with_sequence(sequence).for_chain(&genesis_hash);
+            Transaction::from_wallet(&validator.sign(transaction)).unwrap()
+        };
+
+        // the validator bonds the coin its first block paid it on top of its genesis stake
+        assert!(chain.submit_block(signed_block(&chain, &address, &[])));
+        let stake = signed(crate::wallet::TransactionKind::Stake { amount: 1 }, Vec::new(), 0);
+        assert!(chain.submit_block(signed_block(&chain, "payout", &[stake])));
+        assert_eq!(chain.calculate_total_amt(address.clone()), 0);
+        assert_eq!(chain.stakes().stake_of(address.as_bytes()), 101);
+
+        // only the bonded coin comes back, the genesis stake never was in a balance
+        let unstake = signed(crate::wallet::TransactionKind::Unstake { amount: 101 }, Vec::new(), 1);
+        assert!(chain.submit_block(signed_block(&chain, "payout", &[unstake])));
+        assert_eq!(chain.stakes().stake_of(address.as_bytes()), 0);
+        assert_eq!(chain.calculate_total_amt(address.clone()), 1);
+
+        let output = crate::wallet::TransactionOutput { recipient: "payout".to_string(), amount: 2 };
+        let spend = signed(crate::wallet::TransactionKind::Transfer, vec![output], 2);
+        assert!(!chain.submit_block(signed_block(&chain, "payout", &[spend])));
+    }
+
+    #[test]
+    fn reorganize_checks_fork_seals_before_reverting() {
+        let miner = Wallet::new();
+        let mut chain = test_chain(&miner);
+        assert!(chain.submit_block(block_with(&chain, &[])));
+        let tip = chain.tip();
+        let events = chain.subscribe(EventFilter::all());
+
+        // one block claiming far more work than the chain, without the hashing behind it
+        let mut claim = block_on(&chain, &chain.chain[..2], &[]);
+        claim.header.difficulty = 64;
+        assert!(!chain.reorganize(vec![claim]));
+        assert_eq!(chain.tip(), tip);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn header_chain_covers_signed_blocks() {
+        let authority = Wallet::new();
+        let engine = poa::ProofOfAuthority::new(vec![authority.public_key_str()]).unwrap().with_signer(authority);
+        let mut chain = BlockChain::with_consensus("payout".to_string(), MempoolConfig::default(), Box::new(engine));
+        for _ in 0..5 {
+            assert!(chain.mining());
+        }
+
+        let headers = chain.header_chain();
+        assert_eq!(headers.len(), chain.blocks().len());
+        assert_eq!(headers.tip().hash(), chain.tip().hash);
+    }
+
+    #[test]
+    fn reverting_a_block_restores_balances_and_sequences() {
+        let miner = Wallet::new();
+        let recipient = Wallet::new();
+        let mut chain = test_chain(&miner);
+        assert_eq!(chain.next_sequence(&miner.get_address()), 0);
+
+        let spend = Transaction::from_wallet(&miner.sign_transaction(&chain.genesis_hash(), &recipient.get_address(), 1)).unwrap();
+        assert!(chain.submit_block(block_with(&chain, std::slice::from_ref(&spend))));
+        assert_eq!(chain.calculate_total_amt(miner.get_address()), 0);
+        assert_eq!(chain.calculate_total_amt(recipient.get_address()), 1);
+        assert_eq!(chain.next_sequence(&miner.get_address()), 1);
+
+        // the spend goes back to the pool and its sequence is free on chain again
+        assert!(chain.revert_block().is_some());
+        assert_eq!(chain.calculate_total_amt(miner.get_address()), 1);
+        assert_eq!(chain.calculate_total_amt(recipient.get_address()), 0);
+        assert!(chain.mempool().contains(&spend.id()));
+        assert!(chain.submit_block(block_with(&chain, &[spend])));
+    }
+
+    #[test]
+    fn transactions_do_not_replay_across_chains() {
+        // the same miner is paid a coin on two chains with their own genesis blocks
+        let miner = Wallet::new();
+        let recipient = Wallet::new();
+        let mut chain = test_chain(&miner);
+        let mut other = test_chain(&miner);
+        assert_ne!(chain.genesis_hash(), other.genesis_hash());
+
+        let spend = Transaction::from_wallet(&miner.sign_transaction(&chain.genesis_hash(), &recipient.get_address(), 1)).unwrap();
+        assert!(!other.submit_block(block_with(&other, std::slice::from_ref(&spend))));
+        assert!(chain.submit_block(block_with(&chain, &[spend])));
+        assert_eq!(other.calculate_total_amt(recipient.get_address()), 0);
+    }
+
+    #[test]
+    fn finalized_blocks_cannot_be_reverted() {
+        let miner = Wallet::new();
+        let checkpointer = Wallet::new();
+        let mut chain = test_chain(&miner);
+        for _ in 0..2 {
+            assert!(chain.submit_block(block_with(&chain, &[])));
+        }
+        let gadget = FinalityGadget::new(vec![checkpointer.public_key_str()], 2).unwrap();
+        chain = chain.with_finality(gadget);
+        let checkpoint = chain.block_at(2).unwrap().hash();
+        assert!(chain.submit_checkpoint_vote(&CheckpointVote::new(&checkpointer, 2, checkpoint)));
+        assert_eq!(chain.finalized(), Some(ChainTip { height: 2, hash: checkpoint }));
+
+        // a heavier fork from block 1 would revert the final checkpoint
+        let mut ancestors = chain.chain[..2].to_vec();
+        let mut fork = Vec::new();
+        for _ in 0..3 {
+            let block = block_on(&chain, &ancestors, &[]);
+            ancestors.push(block.clone());
+            fork.push(block);
+        }
+        assert!(!chain.reorganize(fork));
 
-        total_amt
+        // block 3 is above the checkpoint, block 2 is not
+        assert!(chain.revert_block().is_some());
+        assert!(chain.revert_block().is_none());
+        assert_eq!(chain.tip().hash, checkpoint);
     }
 }
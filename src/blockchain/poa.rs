@@ -0,0 +1,309 @@
+use crate::blockchain::consensus::{ sign_header, verify_header_signature, ConsensusEngine, ForkChoice };
+use crate::blockchain::hash::{ tagged_hash, AUTHORITY_VOTE_TAG };
+use crate::blockchain::header::BlockHeader;
+use crate::blockchain::Block;
+use crate::wallet::{ verify_signature, Wallet };
+use borsh::{ BorshDeserialize, BorshSerialize };
+use std::any::Any;
+use std::collections::{ HashMap, HashSet };
+use std::fmt;
+use std::sync::Mutex;
+
+/*
+    proof of authority: a set of known keys take turns sealing blocks, the
+    block at height h is sealed by authorities[h % n] signing the header. the
+    set changes through votes the authorities sign and the sealer carries in
+    the header extra, once more than half of the current set votes for the
+    same change it applies from the next block on. every change starts a new
+    epoch and votes only count in the epoch they were cast for, so old votes
+    cannot be replayed
+*/
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AuthorityVote {
+    // hex public keys, like Wallet::public_key_str
+    pub voter: String,
+    pub subject: String,
+    // true to add the subject, false to remove it
+    pub add: bool,
+    pub epoch: u64,
+    pub signature: String,
+}
+
+impl AuthorityVote {
+    pub fn new(wallet: &Wallet, subject: &str, add: bool, epoch: u64) -> Self {
+        let mut vote = AuthorityVote {
+            voter: wallet.public_key_str(),
+            subject: subject.to_string(),
+            add,
+            epoch,
+            signature: String::new(),
+        };
+        vote.signature = wallet.sign_message(&vote.payload());
+        vote
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        let payload = borsh::to_vec(&(&self.subject, self.add, self.epoch)).expect("Borsh serialization failed");
+        tagged_hash(AUTHORITY_VOTE_TAG, &payload).to_vec()
+    }
+
+    pub fn verify_signature(&self) -> bool {
+        verify_signature(&self.voter, &self.signature, &self.payload())
+    }
+}
+
+// the authority set after some run of blocks
+#[derive(Debug, Clone, Default)]
+pub struct AuthoritySet {
+    // sorted so every node agrees on the turn order
+    pub authorities: Vec<String>,
+    pub epoch: u64,
+    // voters for each pending change in this epoch
+    tallies: HashMap<(String, bool), HashSet<String>>,
+}
+
+impl AuthoritySet {
+    fn new(authorities: Vec<String>) -> Self {
+        // hex keys compare in lower case, see Wallet::public_key_str
+        let mut authorities: Vec<String> = authorities
+            .iter()
+            .map(|key| key.to_ascii_lowercase())
+            .collect();
+        authorities.sort();
+        authorities.dedup();
+        AuthoritySet {
+            authorities,
+            epoch: 0,
+            tallies: HashMap::new(),
+        }
+    }
+
+    pub fn scheduled(&self, height: u64) -> Option<&String> {
+        if self.authorities.is_empty() {
+            return None;
+        }
+        self.authorities.get((height % (self.authorities.len() as u64)) as usize)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.authorities.iter().any(|authority| authority == key)
+    }
+
+    fn accepts(&self, vote: &AuthorityVote) -> bool {
+        let subject = vote.subject.to_ascii_lowercase();
+        vote.epoch == self.epoch &&
+            self.contains(&vote.voter) &&
+            // an authority nobody holds the key for could never seal its turn
+            (!vote.add || Wallet::address_from_public_key(&subject).is_some()) &&
+            // adding a member or removing a stranger changes nothing
+            vote.add != self.contains(&subject) &&
+            // the last authority cannot be voted out
+            (vote.add || self.authorities.len() > 1) &&
+            !self.tallies
+                .get(&(subject, vote.add))
+                .is_some_and(|voters| voters.contains(&vote.voter)) &&
+            vote.verify_signature()
+    }
+
+    fn apply(&mut self, vote: &AuthorityVote) -> bool {
+        if !self.accepts(vote) {
+            return false;
+        }
+
+        let subject = vote.subject.to_ascii_lowercase();
+        let proposal = (subject.clone(), vote.add);
+        let voters = self.tallies.entry(proposal).or_default();
+        voters.insert(vote.voter.clone());
+        if voters.len() * 2 > self.authorities.len() {
+            if vote.add {
+                self.authorities.push(subject);
+                self.authorities.sort();
+            } else {
+                self.authorities.retain(|authority| *authority != subject);
+            }
+            self.epoch += 1;
+            self.tallies.clear();
+        }
+        true
+    }
+}
+
+pub struct ProofOfAuthority {
+    genesis_authorities: Vec<String>,
+    // the key this node seals with, None for a node that only validates
+    signer: Option<Wallet>,
+    // votes waiting for this node's turn to seal
+    pending_votes: Mutex<Vec<AuthorityVote>>,
+}
+
+impl fmt::Debug for ProofOfAuthority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProofOfAuthority")
+            .field("genesis_authorities", &self.genesis_authorities)
+            .field("signer", &self.signer.as_ref().map(|wallet| wallet.public_key_str()))
+            .finish()
+    }
+}
+
+impl ProofOfAuthority {
+    pub fn new(authorities: Vec<String>) -> Option<Self> {
+        // every authority must be a valid public key
+        if authorities.is_empty() || authorities.iter().any(|key| Wallet::address_from_public_key(key).is_none()) {
+            return None;
+        }
+
+        Some(ProofOfAuthority {
+            genesis_authorities: authorities,
+            signer: None,
+            pending_votes: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn with_signer(mut self, wallet: Wallet) -> Self {
+        self.signer = Some(wallet);
+        self
+    }
+
+    pub fn authority_set(&self, ancestors: &[Block]) -> AuthoritySet {
+        // replays the votes of every block after genesis
+        let mut set = AuthoritySet::new(self.genesis_authorities.clone());
+        for block in ancestors.iter().skip(1) {
+            for vote in ProofOfAuthority::votes(&block.header).unwrap_or_default() {
+                set.apply(&vote);
+            }
+        }
+        set
+    }
+
+    pub fn submit_vote(&self, vote: AuthorityVote) -> bool {
+        // held until this node seals a block, stale ones are dropped then
+        if !vote.verify_signature() {
+            println!("authority vote has an invalid signature");
+            return false;
+        }
+
+        let mut pending = self.pending_votes.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if pending.contains(&vote) {
+            return false;
+        }
+        pending.push(vote);
+        true
+    }
+
+    fn votes(header: &BlockHeader) -> Option<Vec<AuthorityVote>> {
+        if header.extra.is_empty() {
+            return Some(Vec::new());
+        }
+        borsh::from_slice(&header.extra).ok()
+    }
+}
+
+impl ConsensusEngine for ProofOfAuthority {
+    fn name(&self) -> &'static str {
+        "proof-of-authority"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn next_difficulty(&self, _ancestors: &[Block]) -> u32 {
+        // no work, every block counts the same for fork choice
+        1
+    }
+
+    fn next_validator(&self, ancestors: &[Block]) -> Option<String> {
+        self.authority_set(ancestors)
+            .scheduled(ancestors.len() as u64)
+            .cloned()
+    }
+
+    fn seal(&self, ancestors: &[Block], block: &mut Block) -> Option<u64> {
+        let signer = match &self.signer {
+            Some(signer) => signer,
+            None => {
+                println!("this node has no authority key");
+                return None;
+            }
+        };
+
+        let mut set = self.authority_set(ancestors);
+        if set.scheduled(block.header.height) != Some(&signer.public_key_str()) {
+            println!("it is not this authority's turn to seal");
+            return None;
+        }
+
+        // carry every pending vote that still counts, forget the rest
+        let mut pending = self.pending_votes.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut votes = Vec::<AuthorityVote>::new();
+        pending.retain(|vote| {
+            if set.apply(vote) {
+                votes.push(vote.clone());
+                return false;
+            }
+            vote.epoch >= set.epoch
+        });
+
+        block.header.extra = if votes.is_empty() {
+            Vec::new()
+        } else {
+            borsh::to_vec(&votes).expect("Borsh serialization failed")
+        };
+        sign_header(signer, &mut block.header);
+        Some(1)
+    }
+
+    fn verify_seal(&self, ancestors: &[Block], header: &BlockHeader) -> bool {
+        if header.difficulty != self.next_difficulty(ancestors) {
+            return false;
+        }
+
+        let mut set = self.authority_set(ancestors);
+        let scheduled = match set.scheduled(header.height) {
+            Some(scheduled) => scheduled.clone(),
+            None => {
+                return false;
+            }
+        };
+        if !verify_header_signature(&scheduled, header) {
+            println!("block is not signed by the scheduled authority");
+            return false;
+        }
+
+        // every vote the sealer carried has to count
+        match ProofOfAuthority::votes(header) {
+            Some(votes) => votes.iter().all(|vote| set.apply(vote)),
+            None => false,
+        }
+    }
+
+    fn select_fork(&self, current: &[Block], candidate: &[Block]) -> ForkChoice {
+        // longest chain, a tie keeps the chain we already have
+        if candidate.len() > current.len() {
+            ForkChoice::Switch
+        } else {
+            ForkChoice::Keep
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn votes_only_add_valid_new_keys() {
+        let authority = Wallet::new();
+        let mut set = AuthoritySet::new(vec![authority.public_key_str()]);
+
+        assert!(!set.apply(&AuthorityVote::new(&authority, "not a key", true, 0)));
+        let upper = authority.public_key_str().to_ascii_uppercase();
+        assert!(!set.apply(&AuthorityVote::new(&authority, &upper, true, 0)));
+        assert_eq!(set.authorities, vec![authority.public_key_str()]);
+
+        let newcomer = Wallet::new();
+        assert!(set.apply(&AuthorityVote::new(&authority, &newcomer.public_key_str().to_ascii_uppercase(), true, 0)));
+        assert!(set.contains(&newcomer.public_key_str()));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::blockchain::consensus::{ sign_header, verify_header_signature, ConsensusEngine, ForkChoice };
+use crate::blockchain::hash::{ tagged_hash, BlockHash, PROPOSER_TAG };
+use crate::blockchain::header::BlockHeader;
+use crate::blockchain::stake::{ StakeLedger, Validator };
+use crate::blockchain::Block;
+use crate::wallet::Wallet;
+use std::any::Any;
+use std::fmt;
+
+/*
+    proof of stake: every slot (block height) has one proposer, drawn from the
+    validator set with probability proportional to stake. the draw is seeded by
+    the genesis hash and the slot, so every node picks the same proposer and no
+    proposer can grind its block to be picked again, and the proposer signs the
+    header. the validator set is the genesis validators plus every stake,
+    unstake and slash on the chain before the current epoch started, so the
+    transactions a proposer includes only count from the next epoch. a validator that signs two different headers for the
+    same slot can be slashed with both headers as evidence
+*/
+
+pub struct ProofOfStake {
+    // hex public keys and the stake they start with
+    genesis_validators: Vec<(String, u64)>,
+    // the key this node proposes with, None for a node that only validates
+    signer: Option<Wallet>,
+}
+
+impl fmt::Debug for ProofOfStake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProofOfStake")
+            .field("genesis_validators", &self.genesis_validators)
+            .field("signer", &self.signer.as_ref().map(|wallet| wallet.public_key_str()))
+            .finish()
+    }
+}
+
+impl ProofOfStake {
+    pub const EPOCH_SLOTS: u64 = 32;
+
+    pub fn new(validators: Vec<(String, u64)>) -> Option<Self> {
+        // every validator needs a valid public key and some stake
+        if
+            validators.is_empty() ||
+            validators.iter().any(|(key, stake)| *stake == 0 || Wallet::address_from_public_key(key).is_none())
+        {
+            return None;
+        }
+
+        Some(ProofOfStake {
+            genesis_validators: validators,
+            signer: None,
+        })
+    }
+
+    pub fn with_signer(mut self, wallet: Wallet) -> Self {
+        self.signer = Some(wallet);
+        self
+    }
+
+    pub fn ledger(&self, ancestors: &[Block]) -> StakeLedger {
+        StakeLedger::with_genesis(&self.genesis_validators).replay(ancestors)
+    }
+
+    pub fn proposer(&self, ancestors: &[Block]) -> Option<Validator> {
+        // the proposer of the block on top of ancestors
+        let slot = ancestors.len() as u64;
+        let epoch_start = (slot - slot % ProofOfStake::EPOCH_SLOTS) as usize;
+        let genesis_hash = ancestors.first().map_or(BlockHash::default(), |block| block.hash());
+        self.ledger(&ancestors[..epoch_start]).select(&ProofOfStake::seed(&genesis_hash, slot)).cloned()
+    }
+
+    fn seed(genesis_hash: &BlockHash, slot: u64) -> [u8; 32] {
+        let data = borsh::to_vec(&(genesis_hash, slot)).expect("Borsh serialization failed");
+        tagged_hash(PROPOSER_TAG, &data)
+    }
+}
+
+impl ConsensusEngine for ProofOfStake {
+    fn name(&self) -> &'static str {
+        "proof-of-stake"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn next_difficulty(&self, _ancestors: &[Block]) -> u32 {
+        // no work, every block counts the same for fork choice
+        1
+    }
+
+    fn genesis_stake(&self) -> Vec<(String, u64)> {
+        self.genesis_validators.clone()
+    }
+
+    fn next_validator(&self, ancestors: &[Block]) -> Option<String> {
+        self.proposer(ancestors).map(|validator| validator.public_key)
+    }
+
+    fn seal(&self, ancestors: &[Block], block: &mut Block) -> Option<u64> {
+        let signer = match &self.signer {
+            Some(signer) => signer,
+            None => {
+                println!("this node has no validator key");
+                return None;
+            }
+        };
+
+        if self.next_validator(ancestors) != Some(signer.public_key_str()) {
+            println!("this validator is not the proposer for the slot");
+            return None;
+        }
+        sign_header(signer, &mut block.header);
+        Some(1)
+    }
+
+    fn verify_seal(&self, ancestors: &[Block], header: &BlockHeader) -> bool {
+        if header.difficulty != self.next_difficulty(ancestors) {
+            return false;
+        }
+
+        match self.next_validator(ancestors) {
+            Some(proposer) if verify_header_signature(&proposer, header) => true,
+            _ => {
+                println!("block is not signed by the proposer for the slot");
+                false
+            }
+        }
+    }
+
+    fn select_fork(&self, current: &[Block], candidate: &[Block]) -> ForkChoice {
+        // longest chain, a tie keeps the chain we already have
+        if candidate.len() > current.len() {
+            ForkChoice::Switch
+        } else {
+            ForkChoice::Keep
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proposer_cannot_grind_the_previous_block() {
+        let validators: Vec<(String, u64)> = (0..4).map(|_| (Wallet::new().public_key_str(), 25)).collect();
+        let pos = ProofOfStake::new(validators).unwrap();
+        let mut ancestors: Vec<Block> = (0..5u64).map(|height| Block::new(height, 0, BlockHash::default())).collect();
+
+        let proposer = pos.proposer(&ancestors);
+        for nonce in 1..50 {
+            ancestors[4].header.nonce = nonce;
+            assert_eq!(pos.proposer(&ancestors), proposer);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::blockchain::consensus::{ ConsensusEngine, ForkChoice };
+use crate::blockchain::genesis::GenesisParams;
+use crate::blockchain::header::BlockHeader;
+use crate::blockchain::timestamp::{ self, median_time_past };
+use crate::blockchain::Block;
+use borsh::{ BorshDeserialize, BorshSerialize };
+use sha2::{ Digest, Sha256 };
+use std::any::Any;
+
+/*
+    the hash a header's proof of work is checked against, chosen per network in
+    the genesis params. the block hash that identifies a block stays the tagged
+    header hash whatever the algorithm
+*/
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PowAlgorithm {
+    // the block hash itself
+    #[default]
+    Sha256,
+    // sha256(sha256(header))
+    DoubleSha256,
+    /*
+        fills `memory_kib` of memory with a sha256 chain seeded by the header,
+        then takes `iterations` steps that each hash in an entry picked by the
+        previous step, so every attempt needs the whole buffer at hand
+    */
+    MemoryHard {
+        memory_kib: u32,
+        iterations: u32,
+    },
+}
+
+impl PowAlgorithm {
+    pub fn hash(&self, header: &BlockHeader) -> [u8; 32] {
+        match self {
+            PowAlgorithm::Sha256 => *header.hash().as_bytes(),
+            PowAlgorithm::DoubleSha256 => Sha256::digest(Sha256::digest(header.encode())).into(),
+            PowAlgorithm::MemoryHard { memory_kib, iterations } => {
+                let entries = ((*memory_kib as usize) * 1024 / 32).max(1);
+                let mut memory = Vec::<[u8; 32]>::with_capacity(entries);
+                memory.push(Sha256::digest(header.encode()).into());
+                for idx in 1..entries {
+                    memory.push(Sha256::digest(memory[idx - 1]).into());
+                }
+
+                let mut state = memory[entries - 1];
+                for _ in 0..*iterations {
+                    let pick = (u64::from_le_bytes(state[..8].try_into().unwrap_or_default()) % (entries as u64)) as usize;
+                    let mut hasher = Sha256::new();
+                    hasher.update(state);
+                    hasher.update(memory[pick]);
+                    state = hasher.finalize().into();
+                }
+                state
+            }
+        }
+    }
+
+    pub fn meets_difficulty(&self, header: &BlockHeader) -> bool {
+        self.meets_target(header, header.difficulty)
+    }
+
+    pub fn meets_target(&self, header: &BlockHeader, difficulty: u32) -> bool {
+        // the hash has to start with `difficulty` hex zeroes
+        let hash_str = hex::encode(self.hash(header));
+        let difficulty = (difficulty as usize).min(hash_str.len());
+        hash_str[0..difficulty] == "0".repeat(difficulty)
+    }
+}
+
+// the proof of work hash has to start with `difficulty` hex zeroes
+#[derive(Debug, Clone)]
+pub struct ProofOfWork {
+    difficulty: u32,
+    retarget: Option<Retarget>,
+}
+
+/*
+    every `interval` blocks the difficulty moves one hex zero up or down when
+    the last interval took less than a quarter or more than four times the
+    target spacing. elapsed time is measured between median times past rather
+    than raw timestamps, so a single block with a skewed timestamp cannot
+    swing the difficulty
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct Retarget {
+    pub interval: u64,
+    // target nanoseconds between blocks
+    pub spacing: u128,
+}
+
+impl ProofOfWork {
+    const MIN_DIFFICULTY: u32 = 1;
+    // every hex digit of a 32 byte hash
+    const MAX_DIFFICULTY: u32 = 64;
+
+    pub fn new(difficulty: u32) -> Self {
+        ProofOfWork { difficulty, retarget: None }
+    }
+
+    pub fn with_retarget(mut self, retarget: Retarget) -> Option<Self> {
+        if retarget.interval == 0 || retarget.spacing == 0 {
+            return None;
+        }
+        self.retarget = Some(retarget);
+        Some(self)
+    }
+
+    pub fn block_work(header: &BlockHeader) -> u128 {
+        // expected number of hashes to find the block, 16 per hex zero
+        1u128.checked_shl(header.difficulty.saturating_mul(4)).unwrap_or(u128::MAX)
+    }
+
+    pub fn chain_work(chain: &[Block]) -> u128 {
+        chain
+            .iter()
+            .fold(0u128, |work, block| work.saturating_add(ProofOfWork::block_work(&block.header)))
+    }
+
+    pub fn network_hashrate(chain: &[Block], window: usize) -> Option<f64> {
+        // work of the last `window` blocks over the time they took, in hashes per second
+        let blocks = &chain[chain.len().saturating_sub(window + 1)..];
+        let first = blocks.first()?.header.time_stamp;
+        let last = blocks.last()?.header.time_stamp;
+        let span = last.checked_sub(first).filter(|span| *span > 0)?;
+        Some((ProofOfWork::chain_work(&blocks[1..]) as f64) / ((span as f64) / 1e9))
+    }
+}
+
+impl ConsensusEngine for ProofOfWork {
+    fn name(&self) -> &'static str {
+        "proof-of-work"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn next_difficulty(&self, ancestors: &[Block]) -> u32 {
+        let retarget = match self.retarget {
+            Some(retarget) => retarget,
+            None => {
+                return self.difficulty;
+            }
+        };
+
+        // genesis carries no difficulty, the first block starts at the configured one
+        let height = ancestors.len() as u64;
+        let previous = match ancestors.last() {
+            Some(block) if height > 1 => block.header.difficulty,
+            _ => {
+                return self.difficulty;
+            }
+        };
+        if !height.is_multiple_of(retarget.interval) || height <= retarget.interval {
+            return previous;
+        }
+
+        let window_start = ancestors.len() - (retarget.interval as usize);
+        let elapsed = median_time_past(ancestors).saturating_sub(median_time_past(&ancestors[..window_start]));
+        let expected = retarget.spacing.saturating_mul(retarget.interval as u128);
+        if elapsed.saturating_mul(4) < expected {
+            (previous + 1).min(ProofOfWork::MAX_DIFFICULTY)
+        } else if elapsed > expected.saturating_mul(4) {
+            previous.saturating_sub(1).max(ProofOfWork::MIN_DIFFICULTY)
+        } else {
+            previous
+        }
+    }
+
+    fn pow_target(&self, ancestors: &[Block]) -> Option<u32> {
+        Some(self.next_difficulty(ancestors))
+    }
+
+    fn seal(&self, ancestors: &[Block], block: &mut Block) -> Option<u64> {
+        // search nonces until the hash of the network's algorithm meets the difficulty
+        let algorithm = GenesisParams::from_chain(ancestors).pow_algorithm;
+        let mut attempts = 1u64;
+        while !algorithm.meets_difficulty(&block.header) {
+            attempts += 1;
+            block.header.nonce = match block.header.nonce.checked_add(1) {
+                Some(nonce) => nonce,
+                None => {
+                    // every nonce tried, a later timestamp gives new headers
+                    block.header.time_stamp = timestamp::now().max(block.header.time_stamp + 1);
+                    i32::MIN
+                }
+            };
+        }
+        Some(attempts)
+    }
+
+    fn verify_seal(&self, ancestors: &[Block], header: &BlockHeader) -> bool {
+        header.difficulty == self.next_difficulty(ancestors) &&
+            GenesisParams::from_chain(ancestors).pow_algorithm.meets_difficulty(header)
+    }
+
+    fn select_fork(&self, current: &[Block], candidate: &[Block]) -> ForkChoice {
+        // most accumulated work wins, a tie keeps the chain we already have
+        if ProofOfWork::chain_work(candidate) > ProofOfWork::chain_work(current) {
+            ForkChoice::Switch
+        } else {
+            ForkChoice::Keep
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::hash::BlockHash;
+
+    #[test]
+    fn retarget_stops_at_max_difficulty() {
+        // blocks a nanosecond apart against a one second target push the difficulty up
+        let pow = ProofOfWork::new(ProofOfWork::MAX_DIFFICULTY)
+            .with_retarget(Retarget { interval: 2, spacing: 1_000_000_000 })
+            .unwrap();
+        let ancestors: Vec<Block> = (0..4u64)
+            .map(|height| {
+                let mut block = Block::new(height, 0, BlockHash::default());
+                block.header.time_stamp = height as u128;
+                block.header.difficulty = ProofOfWork::MAX_DIFFICULTY;
+                block
+            })
+            .collect();
+        assert_eq!(pow.next_difficulty(&ancestors), ProofOfWork::MAX_DIFFICULTY);
+    }
+
+    #[test]
+    fn seal_counts_attempts_across_a_nonce_wrap() {
+        let pow = ProofOfWork::new(2);
+        let ancestors = vec![Block::new(0, 0, BlockHash::default())];
+        let mut block = Block::new(1, i32::MAX, ancestors[0].hash());
+        block.header.difficulty = 2;
+        // a header the last nonce does not solve, so the seal has to wrap
+        while PowAlgorithm::Sha256.meets_difficulty(&block.header) {
+            block.header.time_stamp += 1;
+        }
+
+        let attempts = pow.seal(&ancestors, &mut block).unwrap();
+        // i32::MAX, then i32::MIN up to the nonce that worked
+        assert_eq!(attempts, 2 + ((block.header.nonce as i64) - (i32::MIN as i64)) as u64);
+    }
+}
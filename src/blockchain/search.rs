@@ -0,0 +1,129 @@
+use crate::blockchain::hash::{ BlockHash, TxId };
+use crate::blockchain::transaction::Transaction;
+use crate::blockchain::{ Block, BlockChain, Serialization };
+use std::ops::Range;
+
+#[derive(Debug, Clone)]
+pub enum BlockSearch {
+    // tag value
+    SearchByIndex(usize),
+    SearchByPreviousHash(BlockHash),
+    SearchByBlockHash(BlockHash),
+    SearchByNonce(i32),
+    SearchByTimestamp(u128),
+    SearchByTransaction(TxId),
+    // both ends are included
+    SearchByHeightRange(u64, u64),
+    SearchByTimestampRange(u128, u128),
+    // blocks matching every criterion
+    SearchAll(Vec<BlockSearch>),
+    // blocks matching at least one criterion
+    SearchAny(Vec<BlockSearch>),
+}
+
+#[derive(Debug)]
+pub enum BlockSearchResult<'a> {
+    // every matching block, lowest height first
+    Success(Vec<&'a Block>),
+    FailOfIndex(usize),
+    FailOfPreviousHash(BlockHash),
+    FailOfBlockHash(BlockHash),
+    FailOfNonce(i32),
+    FailOfTimeStamp(u128),
+    FailOfTransaction(TxId),
+    FailOfHeightRange(u64, u64),
+    FailOfTimestampRange(u128, u128),
+    /*
+    the criteria of a combination that match no block on their own, for
+    SearchAll this is empty when each criterion matches some block but no
+    block matches them all
+    */
+    FailOfCombination(Vec<BlockSearchResult<'a>>),
+}
+
+impl BlockSearch {
+    pub fn matches(&self, block: &Block) -> bool {
+        match self {
+            BlockSearch::SearchByIndex(index) => block.height() == (*index as u64),
+            BlockSearch::SearchByPreviousHash(hash) => block.header.previous_hash == *hash,
+            BlockSearch::SearchByBlockHash(hash) => block.hash() == *hash,
+            BlockSearch::SearchByNonce(nonce) => block.header.nonce == *nonce,
+            BlockSearch::SearchByTimestamp(time_stamp) => block.header.time_stamp == *time_stamp,
+            BlockSearch::SearchByTransaction(id) =>
+                block.transactions.iter().any(|tx| Transaction::deserialization(tx.clone()).id() == *id),
+            BlockSearch::SearchByHeightRange(from, to) => (*from..=*to).contains(&block.height()),
+            BlockSearch::SearchByTimestampRange(from, to) => (*from..=*to).contains(&block.header.time_stamp),
+            BlockSearch::SearchAll(searches) => searches.iter().all(|search| search.matches(block)),
+            BlockSearch::SearchAny(searches) => searches.iter().any(|search| search.matches(block)),
+        }
+    }
+
+    fn not_found<'a>(&self, chain: &'a BlockChain) -> BlockSearchResult<'a> {
+        match self {
+            BlockSearch::SearchByIndex(index) => BlockSearchResult::FailOfIndex(*index),
+            BlockSearch::SearchByPreviousHash(hash) => BlockSearchResult::FailOfPreviousHash(*hash),
+            BlockSearch::SearchByBlockHash(hash) => BlockSearchResult::FailOfBlockHash(*hash),
+            BlockSearch::SearchByNonce(nonce) => BlockSearchResult::FailOfNonce(*nonce),
+            BlockSearch::SearchByTimestamp(time_stamp) => BlockSearchResult::FailOfTimeStamp(*time_stamp),
+            BlockSearch::SearchByTransaction(id) => BlockSearchResult::FailOfTransaction(*id),
+            BlockSearch::SearchByHeightRange(from, to) => BlockSearchResult::FailOfHeightRange(*from, *to),
+            BlockSearch::SearchByTimestampRange(from, to) => BlockSearchResult::FailOfTimestampRange(*from, *to),
+            BlockSearch::SearchAll(searches) | BlockSearch::SearchAny(searches) => {
+                let failures = searches
+                    .iter()
+                    .filter(|search| chain.query(search).next().is_none())
+                    .map(|search| search.not_found(chain))
+                    .collect();
+                BlockSearchResult::FailOfCombination(failures)
+            }
+        }
+    }
+}
+
+impl BlockChain {
+    pub fn search_block(&self, search: BlockSearch) -> BlockSearchResult<'_> {
+        let blocks: Vec<&Block> = self.query(&search).collect();
+        if blocks.is_empty() {
+            return search.not_found(self);
+        }
+        BlockSearchResult::Success(blocks)
+    }
+
+    pub fn query<'a: 's, 's>(&'a self, search: &'s BlockSearch) -> impl Iterator<Item = &'a Block> + 's {
+        /*
+        lazily walks the blocks matching the search from the lowest height up,
+        page with skip and take. only the heights the indices allow are visited
+        */
+        let heights = self.search_heights(search);
+        self.chain[heights.start as usize..heights.end as usize]
+            .iter()
+            .filter(move |block| search.matches(block))
+    }
+
+    fn search_heights(&self, search: &BlockSearch) -> Range<u64> {
+        // the heights that can hold a match, narrowed with the height and hash indices
+        let len = self.chain.len() as u64;
+        let single = |height: Option<u64>| match height {
+            Some(height) if height < len => height..height + 1,
+            _ => 0..0,
+        };
+        match search {
+            BlockSearch::SearchByIndex(index) => single(Some(*index as u64)),
+            BlockSearch::SearchByBlockHash(hash) => single(self.height_of(hash)),
+            // a previous hash that is not on the chain can only be the genesis block's
+            BlockSearch::SearchByPreviousHash(hash) => single(Some(self.height_of(hash).map_or(0, |height| height + 1))),
+            BlockSearch::SearchByTransaction(id) => single(self.tx_index.location(id).map(|location| location.height)),
+            BlockSearch::SearchByHeightRange(from, to) => {
+                let end = to.saturating_add(1).min(len);
+                (*from).min(end)..end
+            }
+            BlockSearch::SearchAll(searches) =>
+                searches.iter().fold(0..len, |heights, search| {
+                    let narrowed = self.search_heights(search);
+                    let start = heights.start.max(narrowed.start);
+                    start..heights.end.min(narrowed.end).max(start)
+                }),
+            _ => 0..len,
+        }
+    }
+}
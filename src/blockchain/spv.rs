@@ -0,0 +1,118 @@
+use crate::blockchain::hash::TxId;
+use crate::blockchain::header::{ BlockHeader, HeaderChain };
+use crate::blockchain::merkle::MerkleProof;
+use crate::blockchain::transaction::Transaction;
+
+/*
+    simplified payment verification: a light client only keeps block headers,
+    checks their proof of work and linkage, and trusts a transaction once a full
+    node shows a merkle path from it to the root of a header it already has
+*/
+
+// served by a full node, see BlockChain::transaction_proof
+#[derive(Debug, Clone)]
+pub struct TransactionProof {
+    pub height: u64,
+    pub transaction: Vec<u8>,
+    pub proof: MerkleProof,
+}
+
+#[derive(Debug)]
+pub struct LightClient {
+    headers: HeaderChain,
+    // headers claiming less work than this are refused
+    min_difficulty: u32,
+}
+
+impl LightClient {
+    pub fn new(genesis: BlockHeader, min_difficulty: u32) -> Self {
+        LightClient {
+            headers: HeaderChain::new(genesis),
+            min_difficulty,
+        }
+    }
+
+    pub fn sync(&mut self, headers: &[BlockHeader]) -> usize {
+        // accepts headers in order until one fails, returns how many were added
+        let mut added = 0;
+        for header in headers.iter() {
+            if header.height <= self.headers.tip().height {
+                continue;
+            }
+
+            if header.difficulty < self.min_difficulty {
+                println!("header difficulty {} is below {}", header.difficulty, self.min_difficulty);
+                break;
+            }
+
+            if !self.headers.push(header.clone()) {
+                break;
+            }
+            added += 1;
+        }
+        added
+    }
+
+    pub fn tip(&self) -> &BlockHeader {
+        self.headers.tip()
+    }
+
+    pub fn headers(&self) -> &HeaderChain {
+        &self.headers
+    }
+
+    pub fn confirmations(&self, height: u64) -> u64 {
+        // the block at the tip has one confirmation
+        let tip = self.headers.tip().height;
+        if height > tip {
+            return 0;
+        }
+        tip - height + 1
+    }
+
+    pub fn verify_inclusion(&self, proof: &TransactionProof) -> bool {
+        let header = match self.headers.get(proof.height) {
+            Some(header) => header,
+            None => {
+                println!("no header at height {}", proof.height);
+                return false;
+            }
+        };
+
+        proof.proof.verify(&proof.transaction, &header.merkle_root)
+    }
+
+    pub fn verify_payment(&self, proof: &TransactionProof, address: &str, min_amount: u64) -> bool {
+        // the transaction is in the chain and pays the address at least min_amount
+        if !self.verify_inclusion(proof) {
+            println!("transaction is not included in the chain");
+            return false;
+        }
+
+        // the bytes come from a full node we do not trust
+        let transaction = match Transaction::try_deserialization(&proof.transaction) {
+            Some(transaction) => transaction,
+            None => {
+                println!("proof carries a malformed transaction");
+                return false;
+            }
+        };
+        let paid: u64 = transaction.outputs
+            .iter()
+            .filter(|output| output.recipient_address == address.as_bytes())
+            .fold(0u64, |paid, output| paid.saturating_add(output.value));
+        if paid < min_amount {
+            println!("transaction pays {} to the address, expected {}", paid, min_amount);
+            return false;
+        }
+
+        true
+    }
+}
+
+impl TransactionProof {
+    pub fn id(&self) -> Option<TxId> {
+        // None if the proof does not carry a valid transaction
+        Transaction::try_deserialization(&self.transaction).map(|transaction| transaction.id())
+    }
+}
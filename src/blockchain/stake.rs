@@ -0,0 +1,202 @@
+use crate::blockchain::consensus::verify_header_signature;
+use crate::blockchain::header::BlockHeader;
+use crate::blockchain::transaction::{ Transaction, TransactionKind };
+use crate::blockchain::{ Block, Serialization };
+use crate::wallet::Wallet;
+use borsh::{ BorshDeserialize, BorshSerialize };
+use std::collections::{ BTreeMap, HashSet };
+
+// two different headers for the same height signed by the same key
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone)]
+pub struct DoubleSignEvidence {
+    pub public_key: String,
+    pub first: BlockHeader,
+    pub second: BlockHeader,
+}
+
+impl DoubleSignEvidence {
+    pub fn new(public_key: &str, first: BlockHeader, second: BlockHeader) -> Option<Self> {
+        let evidence = DoubleSignEvidence {
+            public_key: public_key.to_string(),
+            first,
+            second,
+        };
+        if evidence.is_valid() { Some(evidence) } else { None }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.first.height == self.second.height &&
+            self.first.signing_hash() != self.second.signing_hash() &&
+            verify_header_signature(&self.public_key, &self.first) &&
+            verify_header_signature(&self.public_key, &self.second)
+    }
+
+    pub fn offender(&self) -> Option<Vec<u8>> {
+        Wallet::address_from_public_key(&self.public_key).map(|address| address.into_bytes())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validator {
+    pub address: Vec<u8>,
+    pub public_key: String,
+    pub stake: u64,
+    // the part of the stake bonded at genesis, no balance ever paid for it
+    pub genesis_stake: u64,
+}
+
+/*
+    who has how much stake, built by replaying stake, unstake and slash
+    transactions. staked value leaves the sender's balance while it is locked,
+    unstaking gives it back, slashing burns all of it and bars the validator
+    from staking again. stake bonded at genesis can be unstaked too, but it
+    was never part of a balance so it is not paid back to one, see released
+*/
+#[derive(Debug, Clone, Default)]
+pub struct StakeLedger {
+    // ordered by address so every node walks validators the same way
+    validators: BTreeMap<Vec<u8>, Validator>,
+    slashed: HashSet<Vec<u8>>,
+}
+
+impl StakeLedger {
+    pub fn new() -> Self {
+        StakeLedger::default()
+    }
+
+    pub fn with_genesis(validators: &[(String, u64)]) -> Self {
+        let mut ledger = StakeLedger::new();
+        for (key, stake) in validators.iter() {
+            if !ledger.bond(key, *stake) {
+                continue;
+            }
+            if let Some(address) = Wallet::address_from_public_key(key) {
+                if let Some(validator) = ledger.validators.get_mut(address.as_bytes()) {
+                    validator.genesis_stake += stake;
+                }
+            }
+        }
+        ledger
+    }
+
+    pub fn replay(mut self, blocks: &[Block]) -> Self {
+        for block in blocks.iter() {
+            for tx in block.transactions.iter() {
+                self.apply(&Transaction::deserialization(tx.clone()));
+            }
+        }
+        self
+    }
+
+    pub fn bond(&mut self, public_key: &str, value: u64) -> bool {
+        let address = match Wallet::address_from_public_key(public_key) {
+            Some(address) => address.into_bytes(),
+            None => {
+                return false;
+            }
+        };
+        if self.slashed.contains(&address) {
+            return false;
+        }
+
+        let validator = self.validators.entry(address.clone()).or_insert(Validator {
+            address,
+            public_key: public_key.to_string(),
+            stake: 0,
+            genesis_stake: 0,
+        });
+        match validator.stake.checked_add(value) {
+            Some(stake) if validator.public_key == public_key => {
+                validator.stake = stake;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn apply(&mut self, tx: &Transaction) -> bool {
+        // false if the transaction is not a valid stake change, other kinds pass
+        match &tx.kind {
+            TransactionKind::Stake { public_key, value } => {
+                // only the owner of the key can stake for it
+                Wallet::address_from_public_key(public_key).is_some_and(|address| address.as_bytes() == tx.sender_address) &&
+                    *value > 0 &&
+                    self.bond(public_key, *value)
+            }
+            TransactionKind::Unstake { value } => {
+                match self.validators.get_mut(&tx.sender_address) {
+                    Some(validator) if *value > 0 && validator.stake >= *value => {
+                        // stake bonded by transactions goes first, the rest comes out of genesis stake
+                        let bonded = validator.stake - validator.genesis_stake;
+                        validator.genesis_stake -= value.saturating_sub(bonded);
+                        validator.stake -= value;
+                        if validator.stake == 0 {
+                            self.validators.remove(&tx.sender_address);
+                        }
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            TransactionKind::Slash(evidence) => {
+                match evidence.offender() {
+                    Some(offender) if evidence.is_valid() && self.slashed.insert(offender.clone()) => {
+                        self.validators.remove(&offender);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            _ => true,
+        }
+    }
+
+    pub fn released(&self, tx: &Transaction) -> u64 {
+        // what an unstake pays back to the sender's balance, to be asked before
+        // it is applied: only stake that came out of a balance goes back to one
+        match &tx.kind {
+            TransactionKind::Unstake { value } => {
+                match self.validators.get(&tx.sender_address) {
+                    Some(validator) if *value > 0 && validator.stake >= *value => {
+                        (*value).min(validator.stake - validator.genesis_stake)
+                    }
+                    _ => 0,
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn stake_of(&self, address: &[u8]) -> u64 {
+        self.validators.get(address).map_or(0, |validator| validator.stake)
+    }
+
+    pub fn is_slashed(&self, address: &[u8]) -> bool {
+        self.slashed.contains(address)
+    }
+
+    pub fn validators(&self) -> impl Iterator<Item = &Validator> {
+        self.validators.values()
+    }
+
+    pub fn total_stake(&self) -> u128 {
+        self.validators().map(|validator| validator.stake as u128).sum()
+    }
+
+    pub fn select(&self, seed: &[u8; 32]) -> Option<&Validator> {
+        // picks a validator with probability proportional to its stake
+        let total = self.total_stake();
+        if total == 0 {
+            return None;
+        }
+
+        let mut target = u128::from_le_bytes(seed[..16].try_into().ok()?) % total;
+        for validator in self.validators() {
+            if target < (validator.stake as u128) {
+                return Some(validator);
+            }
+            target -= validator.stake as u128;
+        }
+        None
+    }
+}
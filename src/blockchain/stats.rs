@@ -0,0 +1,146 @@
+use std::time::{ Duration, Instant };
+
+/*
+    what a miner has done so far: hashes tried and time spent hashing per
+    thread, and how long each block it found took from getting the work to
+    solving it. hashrates are hashes per second of hashing time, so an idle
+    miner keeps the rate it had while it was busy
+*/
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ThreadStats {
+    pub hashes: u64,
+    pub busy: Duration,
+}
+
+impl ThreadStats {
+    pub fn hashrate(&self) -> f64 {
+        if self.busy.is_zero() {
+            return 0.0;
+        }
+        self.hashes as f64 / self.busy.as_secs_f64()
+    }
+}
+
+// block counts by time to block, bucket i holds times under 2^i seconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeHistogram {
+    counts: Vec<u64>,
+}
+
+impl TimeHistogram {
+    const BUCKETS: usize = 12;
+
+    pub fn new() -> Self {
+        TimeHistogram {
+            counts: vec![0; TimeHistogram::BUCKETS + 1],
+        }
+    }
+
+    pub fn record(&mut self, time: Duration) {
+        let bucket = (0..TimeHistogram::BUCKETS)
+            .find(|bucket| time < Duration::from_secs(1 << bucket))
+            .unwrap_or(TimeHistogram::BUCKETS);
+        self.counts[bucket] += 1;
+    }
+
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        // (upper bound, count), the last bucket has no upper bound
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(bucket, count)| {
+                let bound = if bucket < TimeHistogram::BUCKETS {
+                    Some(Duration::from_secs(1 << bucket))
+                } else {
+                    None
+                };
+                (bound, *count)
+            })
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+impl Default for TimeHistogram {
+    fn default() -> Self {
+        TimeHistogram::new()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MiningStats {
+    started: Instant,
+    threads: Vec<ThreadStats>,
+    time_to_block: TimeHistogram,
+}
+
+impl MiningStats {
+    pub fn new() -> Self {
+        MiningStats {
+            started: Instant::now(),
+            threads: Vec::new(),
+            time_to_block: TimeHistogram::new(),
+        }
+    }
+
+    pub fn record_hashes(&mut self, thread: usize, hashes: u64, busy: Duration) {
+        if self.threads.len() <= thread {
+            self.threads.resize(thread + 1, ThreadStats::default());
+        }
+        let stats = &mut self.threads[thread];
+        stats.hashes = stats.hashes.saturating_add(hashes);
+        stats.busy += busy;
+    }
+
+    pub fn record_block(&mut self, time_to_block: Duration) {
+        self.time_to_block.record(time_to_block);
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn threads(&self) -> &[ThreadStats] {
+        &self.threads
+    }
+
+    pub fn hashes(&self) -> u64 {
+        self.threads.iter().map(|thread| thread.hashes).sum()
+    }
+
+    pub fn hashrate(&self) -> f64 {
+        // the threads hash side by side, so their rates add up
+        self.threads.iter().map(|thread| thread.hashrate()).sum()
+    }
+
+    pub fn blocks_found(&self) -> u64 {
+        self.time_to_block.total()
+    }
+
+    pub fn time_to_block(&self) -> &TimeHistogram {
+        &self.time_to_block
+    }
+
+    pub fn print(&self) {
+        println!("hashes: {} at {:.0} H/s over {} threads", self.hashes(), self.hashrate(), self.threads.len());
+        for (idx, thread) in self.threads.iter().enumerate() {
+            println!("  thread {}: {} hashes, {:.0} H/s", idx, thread.hashes, thread.hashrate());
+        }
+        println!("blocks found: {}", self.blocks_found());
+        for (bound, count) in self.time_to_block.buckets().filter(|(_, count)| *count > 0) {
+            match bound {
+                Some(bound) => println!("  under {:?}: {}", bound, count),
+                None => println!("  longer: {}", count),
+            }
+        }
+    }
+}
+
+impl Default for MiningStats {
+    fn default() -> Self {
+        MiningStats::new()
+    }
+}
@@ -0,0 +1,51 @@
+use crate::blockchain::header::BlockHeader;
+use crate::blockchain::pow::PowAlgorithm;
+use crate::blockchain::transaction::Transaction;
+use crate::blockchain::{ Block, Serialization };
+
+/*
+    the block the node would mine next, handed to a miner outside the node.
+    the miner only has to search the nonce (and may move the timestamp within
+    the rules) until the proof of work hash meets the difficulty, then give the
+    block back through BlockChain::submit_block
+*/
+#[derive(Debug, Clone)]
+pub struct BlockTemplate {
+    // complete except for the seal, the nonce starts at 0
+    pub block: Block,
+    // the earliest timestamp the block may carry, see timestamp::median_time_past
+    pub min_time_stamp: u128,
+    pub algorithm: PowAlgorithm,
+}
+
+impl BlockTemplate {
+    pub fn header(&self) -> &BlockHeader {
+        &self.block.header
+    }
+
+    pub fn coinbase(&self) -> Option<Transaction> {
+        self.block.transactions.first().map(|tx| Transaction::deserialization(tx.clone()))
+    }
+
+    pub fn transactions(&self) -> Vec<Transaction> {
+        // the pool transactions after the coinbase
+        self.block.transactions
+            .iter()
+            .skip(1)
+            .map(|tx| Transaction::deserialization(tx.clone()))
+            .collect()
+    }
+
+    pub fn fees(&self) -> u64 {
+        self.transactions()
+            .iter()
+            .map(|tx| tx.fee)
+            .sum()
+    }
+
+    pub fn solve(&self, nonce: i32) -> Block {
+        let mut block = self.block.clone();
+        block.header.nonce = nonce;
+        block
+    }
+}
@@ -0,0 +1,54 @@
+use crate::blockchain::header::BlockHeader;
+use crate::blockchain::Block;
+use std::time::SystemTime;
+
+/*
+    block timestamps are nanoseconds since the unix epoch, picked by whoever
+    builds the block. a timestamp has to be later than the median of the
+    previous MEDIAN_TIME_SPAN blocks, so time only moves forward on the chain
+    even if a few blocks lie, and at most MAX_FUTURE_DRIFT ahead of our own
+    clock, so nobody can push the chain time far into the future
+*/
+
+pub const MEDIAN_TIME_SPAN: usize = 11;
+// two hours
+pub const MAX_FUTURE_DRIFT: u128 = 2 * 60 * 60 * 1_000_000_000;
+
+pub fn now() -> u128 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |time| time.as_nanos())
+}
+
+pub fn median_time_past(ancestors: &[Block]) -> u128 {
+    median_of_last(ancestors.iter().map(|block| block.header.time_stamp))
+}
+
+pub fn header_median_time_past(headers: &[BlockHeader]) -> u128 {
+    median_of_last(headers.iter().map(|header| header.time_stamp))
+}
+
+fn median_of_last(times: impl DoubleEndedIterator<Item = u128>) -> u128 {
+    // median of the last MEDIAN_TIME_SPAN timestamps, 0 before the first block
+    let mut times: Vec<u128> = times.rev().take(MEDIAN_TIME_SPAN).collect();
+    if times.is_empty() {
+        return 0;
+    }
+    times.sort();
+    times[times.len() / 2]
+}
+
+pub fn next_time_stamp(ancestors: &[Block], now: u128) -> u128 {
+    // our clock, unless the chain time is already ahead of it
+    now.max(median_time_past(ancestors) + 1)
+}
+
+pub fn check_time_stamp(median_time_past: u128, header: &BlockHeader, now: u128) -> bool {
+    if header.time_stamp <= median_time_past {
+        println!("block timestamp is not after the median of the previous blocks");
+        return false;
+    }
+    if header.time_stamp > now.saturating_add(MAX_FUTURE_DRIFT) {
+        println!("block timestamp is too far in the future");
+        return false;
+    }
+    true
+}
@@ -1,22 +1,233 @@
 use crate::blockchain::*;
+use crate::blockchain::hash::{ tagged_hash, BlockHash, TxId, TRANSACTION_TAG };
+use crate::blockchain::stake::DoubleSignEvidence;
+use crate::wallet::{ Transaction as WalletTransaction, TransactionKind as WalletTransactionKind, Wallet };
 use borsh::{ BorshDeserialize, BorshSerialize };
+use serde::{ Deserialize, Serialize };
 use std::fmt;
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
-pub struct Transaction {
-    pub sender_address: Vec<u8>,
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone)]
+pub struct TransactionOutput {
     pub recipient_address: Vec<u8>,
     pub value: u64,
 }
 
+// the earliest block a transaction may be included in
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum LockTime {
+    // chain height of the including block
+    Height(u64),
+    // nanoseconds since the unix epoch, compared with the block time_stamp
+    Timestamp(u128),
+}
+
+// funds locked to a recipient who has to reveal the sha256 preimage of hash_lock
+// before expiry_height, after that the sender can take them back
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone)]
+pub struct HtlcLock {
+    pub recipient_address: Vec<u8>,
+    pub value: u64,
+    pub hash_lock: Vec<u8>,
+    pub expiry_height: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone)]
+pub enum TransactionKind {
+    Transfer,
+    HtlcCreate(HtlcLock),
+    // htlc_id is the id of the transaction that created the htlc
+    HtlcClaim {
+        htlc_id: TxId,
+        preimage: Vec<u8>,
+    },
+    HtlcRefund {
+        htlc_id: TxId,
+    },
+    // locks value from the sender's balance as stake for the key, which has to
+    // be the sender's own
+    Stake {
+        public_key: String,
+        value: u64,
+    },
+    Unstake {
+        value: u64,
+    },
+    Slash(Box<DoubleSignEvidence>),
+    // the block reward, the height keeps every coinbase id unique
+    Coinbase {
+        height: u64,
+    },
+}
+
+impl TransactionKind {
+    pub fn from_wallet(kind: &WalletTransactionKind, public_key: &str) -> Option<TransactionKind> {
+        // the wallet carries binary fields as hex strings, public_key is the signer's
+        let kind = match kind {
+            WalletTransactionKind::Transfer => TransactionKind::Transfer,
+            WalletTransactionKind::HtlcCreate { recipient, amount, hash_lock, expiry_height } => {
+                TransactionKind::HtlcCreate(HtlcLock {
+                    recipient_address: recipient.as_bytes().to_vec(),
+                    value: *amount,
+                    hash_lock: hex::decode(hash_lock).ok()?,
+                    expiry_height: *expiry_height,
+                })
+            }
+            WalletTransactionKind::HtlcClaim { htlc_id, preimage } => {
+                TransactionKind::HtlcClaim {
+                    htlc_id: htlc_id.parse().ok()?,
+                    preimage: hex::decode(preimage).ok()?,
+                }
+            }
+            WalletTransactionKind::HtlcRefund { htlc_id } => {
+                TransactionKind::HtlcRefund {
+                    htlc_id: htlc_id.parse().ok()?,
+                }
+            }
+            WalletTransactionKind::Stake { amount } => {
+                TransactionKind::Stake {
+                    public_key: public_key.to_string(),
+                    value: *amount,
+                }
+            }
+            WalletTransactionKind::Unstake { amount } => TransactionKind::Unstake { value: *amount },
+            WalletTransactionKind::Slash { evidence } => {
+                TransactionKind::Slash(borsh::from_slice(&hex::decode(evidence).ok()?).ok()?)
+            }
+        };
+        Some(kind)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone)]
+pub struct Transaction {
+    pub sender_address: Vec<u8>,
+    pub outputs: Vec<TransactionOutput>,
+    pub lock_time: Option<LockTime>,
+    pub kind: TransactionKind,
+    // paid by the sender to the miner that includes the transaction
+    pub fee: u64,
+    // chosen by the sender, never reused once confirmed, a pending transaction
+    // can be replaced by one with the same sequence and a higher fee
+    pub sequence: u64,
+    // the signed wallet transaction as json, so every node can check who
+    // authorized it, empty for the coinbase
+    pub witness: Vec<u8>,
+}
+
 impl Transaction {
     pub fn new(sender: Vec<u8>, recipient: Vec<u8>, value: u64) -> Transaction {
+        Transaction::new_batch(
+            sender,
+            vec![TransactionOutput {
+                recipient_address: recipient,
+                value,
+            }]
+        )
+    }
+
+    pub fn new_batch(sender: Vec<u8>, outputs: Vec<TransactionOutput>) -> Transaction {
         Transaction {
             sender_address: sender,
-            recipient_address: recipient,
-            value,
+            outputs,
+            lock_time: None,
+            kind: TransactionKind::Transfer,
+            fee: 0,
+            sequence: 0,
+            witness: Vec::new(),
         }
     }
+
+    pub fn from_wallet(tx: &WalletTransaction) -> Option<Transaction> {
+        // the chain form of a signed wallet transaction, None if a field does not convert
+        let kind = TransactionKind::from_wallet(&tx.kind, &tx.public_key)?;
+        let outputs = tx.outputs
+            .iter()
+            .map(|output| TransactionOutput {
+                recipient_address: output.recipient.as_bytes().to_vec(),
+                value: output.amount,
+            })
+            .collect();
+        let mut transaction = Transaction::new_batch(tx.sender.as_bytes().to_vec(), outputs);
+        transaction.lock_time = tx.lock_time;
+        transaction.kind = kind;
+        transaction.fee = tx.fee;
+        transaction.sequence = tx.sequence;
+        transaction.witness = serde_json::to_vec(tx).ok()?;
+        Some(transaction)
+    }
+
+    pub fn verify_authorization(&self, genesis_hash: &BlockHash) -> bool {
+        // the witness has to carry valid signatures for the chain with genesis_hash
+        // and describe exactly this transaction
+        let signed = match serde_json::from_slice::<WalletTransaction>(&self.witness) {
+            Ok(signed) => signed,
+            Err(_) => {
+                return false;
+            }
+        };
+        signed.genesis_hash == genesis_hash.to_string() &&
+            Wallet::verify_transaction(&signed) &&
+            Transaction::from_wallet(&signed).as_ref() == Some(self)
+    }
+
+    pub fn id(&self) -> TxId {
+        // the borsh serialization is the canonical encoding of a transaction
+        TxId(tagged_hash(TRANSACTION_TAG, &self.serialization()))
+    }
+
+    pub fn is_final(&self, height: u64, time_stamp: u128) -> bool {
+        // a transaction without lock time can go into any block
+        match self.lock_time {
+            None => true,
+            Some(LockTime::Height(lock_height)) => height >= lock_height,
+            Some(LockTime::Timestamp(lock_time_stamp)) => time_stamp >= lock_time_stamp,
+        }
+    }
+
+    pub fn total_value(&self) -> u64 {
+        // outputs are checked for overflow before the transaction enters the pool,
+        // see checked_total_value for transactions from anywhere else
+        self.checked_total_value().unwrap_or(u64::MAX)
+    }
+
+    pub fn checked_total_value(&self) -> Option<u64> {
+        self.outputs.iter().try_fold(0u64, |total, output| total.checked_add(output.value))
+    }
+
+    pub fn spent_value(&self) -> u64 {
+        self.checked_spent_value().unwrap_or(u64::MAX)
+    }
+
+    pub fn checked_spent_value(&self) -> Option<u64> {
+        // what leaves the sender's balance, an htlc or stake locks its value
+        let locked = match &self.kind {
+            TransactionKind::HtlcCreate(lock) => lock.value,
+            TransactionKind::Stake { value, .. } => *value,
+            _ => 0,
+        };
+        self.checked_total_value()?.checked_add(locked)?.checked_add(self.fee)
+    }
+
+    pub fn try_deserialization(bytes: &[u8]) -> Option<Transaction> {
+        // for bytes from outside the node, deserialization panics on malformed input
+        Transaction::try_from_slice(bytes).ok()
+    }
+
+    pub fn addresses(&self) -> Vec<&[u8]> {
+        // the sender and everyone the transaction pays or locks value for
+        let mut addresses = vec![self.sender_address.as_slice()];
+        for output in self.outputs.iter() {
+            addresses.push(&output.recipient_address);
+        }
+        if let TransactionKind::HtlcCreate(lock) = &self.kind {
+            addresses.push(&lock.recipient_address);
+        }
+        addresses
+    }
+
+    pub fn involves(&self, address: &[u8]) -> bool {
+        self.addresses().contains(&address)
+    }
 }
 
 impl Serialization<Transaction> for Transaction {
@@ -75,22 +286,26 @@ impl Serialization<Transaction> for Transaction {
         //     value,
         // }
 
-        let tx = Transaction::try_from_slice(&bytes).expect("Borsh deserialization failed");
-
-        tx
+        Transaction::try_from_slice(&bytes).expect("Borsh deserialization failed")
     }
 }
 
 impl fmt::Display for Transaction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}\nsender address: {:?}\nrecipient address:{:?}\nvalue:{}\n{}\n",
-            "-".repeat(40),
-            self.sender_address,
-            self.recipient_address,
-            self.value,
-            "-".repeat(40)
-        )
+        writeln!(f, "{}\nsender address: {:?}", "-".repeat(40), self.sender_address)?;
+        for output in self.outputs.iter() {
+            writeln!(f, "recipient address:{:?}\nvalue:{}", output.recipient_address, output.value)?;
+        }
+        if let Some(lock_time) = self.lock_time {
+            writeln!(f, "lock time:{:?}", lock_time)?;
+        }
+        if self.fee > 0 {
+            writeln!(f, "fee:{}", self.fee)?;
+        }
+        writeln!(f, "sequence:{}", self.sequence)?;
+        if self.kind != TransactionKind::Transfer {
+            writeln!(f, "kind:{:?}", self.kind)?;
+        }
+        writeln!(f, "{}", "-".repeat(40))
     }
 }
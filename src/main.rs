@@ -1,15 +1,68 @@
 pub mod wallet;
-use wallet::Wallet;
+use wallet::{ multisig::MultisigPolicy, Transaction, Wallet };
 pub mod blockchain;
-use blockchain::BlockChain;
+use blockchain::{
+    mempool::MempoolConfig,
+    finality::{ CheckpointVote, FinalityGadget },
+    genesis::GenesisParams,
+    hash::BlockHash,
+    poa::ProofOfAuthority,
+    pow::{ PowAlgorithm, ProofOfWork },
+    pos::ProofOfStake,
+    spv::LightClient,
+    transaction::Transaction as ChainTransaction,
+    BlockChain,
+    Serialization,
+};
+pub mod node;
+use node::{ pool::{ mine_for_pool, MiningPool }, Node };
 
 fn main() {
+    /*
+    `node [listen address] [pool address]` runs a node with the explorer, and a
+    mining pool when given a pool address, instead of the demo. `miner <pool
+    address> <name>` mines shares for a pool, `stats [node address]` shows the
+    mining stats of a running node
+    */
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|arg| arg.as_str()) == Some("node") {
+        run_node(
+            args.get(2).map_or("127.0.0.1:8080", |address| address.as_str()),
+            args.get(3).map(|address| address.as_str())
+        );
+        return;
+    }
+    if args.get(1).map(|arg| arg.as_str()) == Some("stats") {
+        let address = args.get(2).map_or("127.0.0.1:8080", |address| address.as_str());
+        match node::http::get(address, "/api/miner/stats") {
+            Ok(body) =>
+                match serde_json::from_str::<serde_json::Value>(&body) {
+                    Ok(stats) => println!("{}", serde_json::to_string_pretty(&stats).unwrap_or(body)),
+                    Err(_) => println!("{}", body),
+                }
+            Err(e) => println!("cannot reach node {}: {}", address, e),
+        }
+        return;
+    }
+    if args.get(1).map(|arg| arg.as_str()) == Some("miner") {
+        match (args.get(2), args.get(3)) {
+            (Some(address), Some(name)) => {
+                if let Err(e) = mine_for_pool(address, name) {
+                    println!("miner stopped: {}", e);
+                }
+            }
+            _ => println!("usage: miner <pool address> <name>"),
+        }
+        return;
+    }
+
     let wallet = Wallet::new();
     println!("private key: {}", wallet.private_key_str());
     println!("public key: {}", wallet.public_key_str());
     println!("address: {}", wallet.get_address());
 
-    let transaction = wallet.sign_transaction(&"0x1234567890".to_string(), 100);
+    // signed for no chain in particular, only to show the signature
+    let transaction = wallet.sign_transaction(&BlockHash::default(), "0x1234567890", 100);
     println!("transaction : {:?}", transaction);
     println!("verify: {}", Wallet::verify_transaction(&transaction));
 
@@ -17,13 +70,114 @@ fn main() {
     let wallet_a = Wallet::new();
     let wallet_b = Wallet::new();
 
-    let tx_a_b = wallet_a.sign_transaction(&wallet_b.get_address(), 100);
+    let treasury = MultisigPolicy::new(
+        vec![wallet_miner.public_key_str(), wallet_a.public_key_str(), wallet_b.public_key_str()],
+        2
+    ).unwrap();
+    println!("treasury address: {}", treasury.address());
+    let mut tx_treasury = Transaction::new_multisig(treasury, Vec::new());
+    wallet_a.cosign_transaction(&mut tx_treasury);
+    println!("verify 1 of 2: {}", Wallet::verify_transaction(&tx_treasury));
+    wallet_b.cosign_transaction(&mut tx_treasury);
+    println!("verify 2 of 2: {}", Wallet::verify_transaction(&tx_treasury));
+
     let mut blockchain = BlockChain::new(wallet_miner.get_address());
+    let tx_a_b = wallet_a.sign_transaction(&blockchain.genesis_hash(), &wallet_b.get_address(), 100);
     let is_add = blockchain.add_transaction(&tx_a_b);
     println!("Added: {}", is_add);
     blockchain.mining();
     blockchain.print();
+    let tip = blockchain.tip();
+    println!("chain tip: height {} hash {}\n", tip.height, tip.hash);
     println!("A: {:?}\n", blockchain.calculate_total_amt(wallet_a.get_address()));
     println!("B: {:?}\n", blockchain.calculate_total_amt(wallet_b.get_address()));
     println!("Miner: {:?}\n", blockchain.calculate_total_amt(wallet_miner.get_address()));
+    for receipt in blockchain.address_history(&wallet_miner.get_address(), 0, 10) {
+        println!(
+            "miner receipt {} at height {} ({} confirmations)",
+            receipt.id,
+            receipt.height,
+            receipt.confirmations
+        );
+    }
+
+    // a light client syncs headers only and checks the miner got paid in the last block
+    let mut light_client = LightClient::new(blockchain.header(0).unwrap().clone(), 5);
+    let headers: Vec<_> = blockchain.headers(1, 100).into_iter().cloned().collect();
+    println!("light client synced {} headers", light_client.sync(&headers));
+    let coinbase = ChainTransaction::deserialization(blockchain.last_block().transactions[0].clone());
+    let proof = blockchain.transaction_proof(&coinbase.id()).unwrap();
+    println!(
+        "light client verified miner payment: {}",
+        light_client.verify_payment(&proof, &wallet_miner.get_address(), 1)
+    );
+    blockchain.mining_stats().print();
+    if let Some(hashrate) = blockchain.estimated_network_hashrate() {
+        println!("estimated network hashrate: {:.0} H/s", hashrate);
+    }
+
+    // a private network sealed by a single authority instead of proof of work
+    let authority = Wallet::new();
+    let engine = ProofOfAuthority::new(vec![authority.public_key_str()]).unwrap().with_signer(authority);
+    let mut private_chain = BlockChain::with_consensus(
+        wallet_miner.get_address(),
+        MempoolConfig::default(),
+        Box::new(engine)
+    );
+    private_chain.mining();
+    println!("{} chain at height {}", private_chain.consensus().name(), private_chain.tip().height);
+
+    // validators drawn by stake, here a single one bonded at genesis
+    let validator = Wallet::new();
+    let engine = ProofOfStake::new(vec![(validator.public_key_str(), 100)]).unwrap().with_signer(validator);
+    let mut staked_chain = BlockChain::with_consensus(wallet_miner.get_address(), MempoolConfig::default(), Box::new(engine));
+    staked_chain.mining();
+    println!(
+        "{} chain at height {} with {} staked",
+        staked_chain.consensus().name(),
+        staked_chain.tip().height,
+        staked_chain.stakes().total_stake()
+    );
+
+    // a validator checkpoints every other block, with a single validator its vote is a supermajority
+    let checkpointer = Wallet::new();
+    let gadget = FinalityGadget::new(vec![checkpointer.public_key_str()], 2).unwrap();
+    staked_chain = staked_chain.with_finality(gadget);
+    let checkpoint = staked_chain.block_at(2).unwrap().hash();
+    staked_chain.submit_checkpoint_vote(&CheckpointVote::new(&checkpointer, 2, checkpoint));
+    println!("final up to height {:?}", staked_chain.finalized().map(|checkpoint| checkpoint.height));
+
+    // a network whose genesis asks for a memory-hard proof of work
+    let params = GenesisParams::default().with_pow_algorithm(PowAlgorithm::MemoryHard { memory_kib: 64, iterations: 2048 });
+    let memory_hard_chain = BlockChain::with_genesis(
+        wallet_miner.get_address(),
+        MempoolConfig::default(),
+        Box::new(ProofOfWork::new(2)),
+        params
+    );
+    println!(
+        "{:?} chain at height {}",
+        memory_hard_chain.genesis_params().pow_algorithm,
+        memory_hard_chain.tip().height
+    );
+}
+
+fn run_node(listen_address: &str, pool_address: Option<&str>) {
+    let wallet_miner = Wallet::new();
+    println!("miner address: {}", wallet_miner.get_address());
+    let node = Node::new(BlockChain::new(wallet_miner.get_address()));
+    if let Some(pool_address) = pool_address {
+        // pool blocks pay the node's miner address, a share needs two hex zeroes
+        if let Some(pool) = MiningPool::new(node.clone(), wallet_miner.get_address(), 2) {
+            let pool_address = pool_address.to_string();
+            std::thread::spawn(move || {
+                if let Err(e) = pool.serve(&pool_address) {
+                    println!("mining pool stopped: {}", e);
+                }
+            });
+        }
+    }
+    if let Err(e) = node.serve(listen_address) {
+        println!("node stopped: {}", e);
+    }
 }
@@ -0,0 +1,178 @@
+use crate::blockchain::header::BlockHeader;
+use crate::blockchain::pow::PowAlgorithm;
+use crate::blockchain::stats::MiningStats;
+use crate::blockchain::template::BlockTemplate;
+use crate::blockchain::Block;
+use crate::node::http::{ Request, Response };
+use crate::node::miner::MinerSettings;
+use crate::node::Node;
+use crate::node::websocket::transaction_json;
+use serde_json::{ json, Value };
+
+/*
+    json endpoints for miners outside the node:
+
+        GET  /api/template?address=<payout address>  the next block to mine
+        POST /api/block                              a solved block,
+             {"header": "<hex borsh header>", "transactions": ["<hex>", ...]}
+
+    and to control the node's own background miner:
+
+        GET  /api/miner                              settings
+        GET  /api/miner/stats                        hashrates, time to block and
+                                                     the estimated network hashrate
+        POST /api/miner                              change settings,
+             {"threads": 4, "payout_address": "<address>"}, both optional
+        POST /api/miner/start
+        POST /api/miner/stop
+
+    the template lists everything the block commits to, the header field is
+    ready to search nonces on as is
+*/
+
+pub fn route(node: &Node, request: &Request) -> Option<Response> {
+    let response = match (request.method.as_str(), request.path.trim_end_matches('/')) {
+        ("GET", "/api/template") =>
+            match request.query.get("address") {
+                Some(address) if !address.is_empty() =>
+                    Response::json(template_json(&node.chain().get_block_template(address)).to_string()),
+                _ => Response::bad_request("expected a payout address"),
+            }
+        ("POST", "/api/block") =>
+            match block_from_json(&request.body) {
+                Some(block) => {
+                    let mut chain = node.chain();
+                    let accepted = chain.submit_block(block);
+                    Response::json(json!({ "accepted": accepted, "tip": chain.tip().hash.to_string() }).to_string())
+                }
+                None => Response::bad_request("expected a header and transactions"),
+            }
+        ("GET", "/api/miner") => Response::json(settings_json(&node.miner().settings()).to_string()),
+        ("GET", "/api/miner/stats") => {
+            let network_hashrate = node.chain().estimated_network_hashrate();
+            Response::json(stats_json(&node.miner().stats(), network_hashrate).to_string())
+        }
+        ("POST", "/api/miner") => update_miner(node, &request.body),
+        ("POST", "/api/miner/start") => {
+            if !node.miner().start() {
+                return Some(Response::bad_request("this chain cannot be mined"));
+            }
+            Response::json(settings_json(&node.miner().settings()).to_string())
+        }
+        ("POST", "/api/miner/stop") => {
+            node.miner().stop();
+            Response::json(settings_json(&node.miner().settings()).to_string())
+        }
+        | (_, "/api/template")
+        | (_, "/api/block")
+        | (_, "/api/miner")
+        | (_, "/api/miner/stats")
+        | (_, "/api/miner/start")
+        | (_, "/api/miner/stop") => Response::bad_request("method not allowed").with_status(405),
+        _ => {
+            return None;
+        }
+    };
+    Some(response)
+}
+
+fn update_miner(node: &Node, body: &[u8]) -> Response {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(_) => {
+            return Response::bad_request("expected a json object");
+        }
+    };
+
+    if !request["threads"].is_null() {
+        let applied = request["threads"]
+            .as_u64()
+            .is_some_and(|threads| node.miner().set_threads(threads as usize));
+        if !applied {
+            return Response::bad_request("invalid thread count");
+        }
+    }
+    if !request["payout_address"].is_null() {
+        let applied = request["payout_address"]
+            .as_str()
+            .is_some_and(|address| node.miner().set_payout_address(address));
+        if !applied {
+            return Response::bad_request("invalid payout address");
+        }
+    }
+    Response::json(settings_json(&node.miner().settings()).to_string())
+}
+
+fn settings_json(settings: &MinerSettings) -> Value {
+    json!({
+        "running": settings.running,
+        "threads": settings.threads,
+        "payout_address": settings.payout_address,
+    })
+}
+
+pub fn stats_json(stats: &MiningStats, network_hashrate: Option<f64>) -> Value {
+    json!({
+        "uptime_secs": stats.uptime().as_secs(),
+        "hashes": stats.hashes(),
+        "hashrate": stats.hashrate(),
+        "threads": stats.threads()
+            .iter()
+            .map(|thread| json!({ "hashes": thread.hashes, "hashrate": thread.hashrate() }))
+            .collect::<Vec<_>>(),
+        "blocks_found": stats.blocks_found(),
+        // count of blocks found in under `under_secs` seconds, null for the open ended last bucket
+        "time_to_block": stats.time_to_block()
+            .buckets()
+            .map(|(bound, count)| json!({ "under_secs": bound.map(|bound| bound.as_secs()), "count": count }))
+            .collect::<Vec<_>>(),
+        "network_hashrate": network_hashrate,
+    })
+}
+
+fn template_json(template: &BlockTemplate) -> Value {
+    let header = template.header();
+    json!({
+        "height": header.height,
+        "version": header.version,
+        "previous_hash": header.previous_hash.to_string(),
+        "merkle_root": hex::encode(header.merkle_root),
+        "time_stamp": header.time_stamp.to_string(),
+        "min_time_stamp": template.min_time_stamp.to_string(),
+        "difficulty": header.difficulty,
+        "algorithm": algorithm_json(&template.algorithm),
+        "header": hex::encode(header.encode()),
+        "coinbase": template.coinbase().map(|coinbase| transaction_json(&coinbase)),
+        "transactions": template.transactions().iter().map(transaction_json).collect::<Vec<_>>(),
+        "fees": template.fees(),
+        // raw transactions in block order, coinbase first, to send back with the solved header
+        "data": template.block.transactions.iter().map(hex::encode).collect::<Vec<_>>(),
+    })
+}
+
+fn algorithm_json(algorithm: &PowAlgorithm) -> Value {
+    match algorithm {
+        PowAlgorithm::Sha256 => json!({ "type": "sha256" }),
+        PowAlgorithm::DoubleSha256 => json!({ "type": "double_sha256" }),
+        PowAlgorithm::MemoryHard { memory_kib, iterations } =>
+            json!({
+                "type": "memory_hard",
+                "memory_kib": memory_kib,
+                "iterations": iterations,
+            }),
+    }
+}
+
+fn block_from_json(body: &[u8]) -> Option<Block> {
+    let request: Value = serde_json::from_slice(body).ok()?;
+    let header = hex::decode(request["header"].as_str()?).ok()?;
+    let transactions = request["transactions"]
+        .as_array()?
+        .iter()
+        .map(|tx| tx.as_str().and_then(|tx| hex::decode(tx).ok()))
+        .collect::<Option<Vec<Vec<u8>>>>()?;
+    Some(Block {
+        header: borsh::from_slice::<BlockHeader>(&header).ok()?,
+        transactions,
+    })
+}
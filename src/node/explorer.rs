@@ -0,0 +1,388 @@
+use crate::blockchain::hash::{ BlockHash, TxId };
+use crate::blockchain::transaction::{ LockTime, Transaction, TransactionKind };
+use crate::blockchain::{ BlockChain, Serialization };
+use crate::node::http::{ percent_encode, Request, Response };
+use std::fmt::Write;
+use std::time::Instant;
+
+/*
+    the block explorer, plain server rendered html pages so it works without
+    any javascript:
+        /                   recent blocks
+        /block/<height|hash>
+        /tx/<id>
+        /address/<address>?page=<n>
+        /mempool
+        /search?q=<height|hash|id|address>
+*/
+
+const RECENT_BLOCKS: usize = 20;
+const HISTORY_PAGE: usize = 20;
+
+pub fn route(chain: &BlockChain, request: &Request) -> Option<Response> {
+    let path = request.path.trim_end_matches('/');
+    let response = match path.split('/').skip(1).collect::<Vec<_>>()[..] {
+        [] | [""] => index_page(chain),
+        ["block", key] => block_page(chain, key),
+        ["tx", id] => transaction_page(chain, id),
+        ["address", address] => address_page(chain, address, request.query_usize("page").unwrap_or(0)),
+        ["mempool"] => mempool_page(chain),
+        ["search"] => search(chain, request.query.get("q").map_or("", |q| q.trim())),
+        _ => {
+            return None;
+        }
+    };
+    Some(response)
+}
+
+fn index_page(chain: &BlockChain) -> Response {
+    let tip = chain.tip();
+    let mut body = format!(
+        "<p>tip height {} hash {} &middot; <a href=\"/mempool\">{} pending transactions</a></p>",
+        tip.height,
+        block_link(&tip.hash, &tip.hash.to_string()),
+        chain.mempool().len()
+    );
+    if let Some(checkpoint) = chain.finalized() {
+        let _ = write!(
+            body,
+            "<p>final up to height {}</p>",
+            block_link(&checkpoint.hash, &checkpoint.height.to_string())
+        );
+    }
+    body.push_str("<h2>Recent blocks</h2><table><tr><th>height</th><th>hash</th><th>transactions</th><th>time</th></tr>");
+    let from = tip.height.saturating_sub((RECENT_BLOCKS as u64) - 1);
+    for height in (from..=tip.height).rev() {
+        if let Some(block) = chain.block_at(height) {
+            let _ = write!(
+                body,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                block_link(&block.hash(), &height.to_string()),
+                block_link(&block.hash(), &block.hash().to_string()),
+                block.transactions.len(),
+                time(block.header.time_stamp)
+            );
+        }
+    }
+    body.push_str("</table>");
+    page("Blocks", &body)
+}
+
+fn block_page(chain: &BlockChain, key: &str) -> Response {
+    let block = match key.parse::<u64>() {
+        Ok(height) => chain.block_at(height),
+        Err(_) => key.parse::<BlockHash>().ok().and_then(|hash| chain.block_by_hash(&hash)),
+    };
+    let block = match block {
+        Some(block) => block,
+        None => {
+            return not_found(&format!("no block {}", key));
+        }
+    };
+
+    let header = &block.header;
+    let mut body = String::from("<table>");
+    let mut row = |name: &str, value: String| {
+        let _ = write!(body, "<tr><th>{}</th><td>{}</td></tr>", name, value);
+    };
+    row("height", header.height.to_string());
+    row("hash", block.hash().to_string());
+    row("previous hash", if header.height == 0 {
+        header.previous_hash.to_string()
+    } else {
+        block_link(&header.previous_hash, &header.previous_hash.to_string())
+    });
+    row("merkle root", hex::encode(header.merkle_root));
+    row("time", time(header.time_stamp));
+    row("difficulty", header.difficulty.to_string());
+    row("nonce", header.nonce.to_string());
+    row("version", header.version.to_string());
+    if !header.extra.is_empty() {
+        row("extra", hex::encode(&header.extra));
+    }
+    if !header.seal.is_empty() {
+        row("seal", hex::encode(&header.seal));
+    }
+    body.push_str("</table>");
+
+    if header.height > 0 {
+        let _ = write!(body, "<p><a href=\"/block/{}\">&larr; previous</a>", header.height - 1);
+    } else {
+        body.push_str("<p>");
+    }
+    if chain.block_at(header.height + 1).is_some() {
+        let _ = write!(body, " <a href=\"/block/{}\">next &rarr;</a>", header.height + 1);
+    }
+    body.push_str("</p>");
+
+    let _ = write!(body, "<h2>{} transactions</h2>", block.transactions.len());
+    for tx in block.transactions.iter() {
+        body.push_str(&transaction_table(&Transaction::deserialization(tx.clone())));
+    }
+    page(&format!("Block {}", header.height), &body)
+}
+
+fn transaction_page(chain: &BlockChain, id: &str) -> Response {
+    let id = match id.parse::<TxId>() {
+        Ok(id) => id,
+        Err(e) => {
+            return not_found(&format!("bad transaction id: {}", e));
+        }
+    };
+
+    if let Some(receipt) = chain.receipt(&id) {
+        let body = format!(
+            "<p>confirmed in block {} at position {}, {} confirmations</p>{}",
+            block_link(&receipt.block_hash, &receipt.height.to_string()),
+            receipt.position,
+            receipt.confirmations,
+            transaction_table(&receipt.transaction)
+        );
+        return page("Transaction", &body);
+    }
+
+    match chain.mempool().get(&id) {
+        Some(entry) => {
+            let body = format!(
+                "<p>pending in the <a href=\"/mempool\">mempool</a></p>{}",
+                transaction_table(&entry.transaction)
+            );
+            page("Transaction", &body)
+        }
+        None => not_found(&format!("no transaction {}", id)),
+    }
+}
+
+fn address_page(chain: &BlockChain, address: &str, page_number: usize) -> Response {
+    let total = chain.address_history_len(address);
+    let mut body = String::from("<table>");
+    let _ = write!(body, "<tr><th>address</th><td>{}</td></tr>", escape(address));
+    let _ = write!(body, "<tr><th>balance</th><td>{}</td></tr>", chain.calculate_total_amt(address.to_string()));
+    let _ = write!(body, "<tr><th>locked in htlcs</th><td>{}</td></tr>", chain.calculate_locked_amt(address.to_string()));
+    let _ = write!(body, "<tr><th>next sequence</th><td>{}</td></tr>", chain.next_sequence(address));
+    let _ = write!(body, "<tr><th>transactions</th><td>{}</td></tr>", total);
+    body.push_str("</table>");
+
+    let pending: Vec<_> = chain
+        .mempool()
+        .entries()
+        .filter(|entry| {
+            entry.transaction.sender_address == address.as_bytes() ||
+                entry.transaction.outputs.iter().any(|output| output.recipient_address == address.as_bytes())
+        })
+        .collect();
+    if !pending.is_empty() {
+        body.push_str("<h2>Pending</h2><table><tr><th>id</th><th>fee</th></tr>");
+        for entry in pending {
+            let _ = write!(body, "<tr><td>{}</td><td>{}</td></tr>", tx_link(&entry.id), entry.fee());
+        }
+        body.push_str("</table>");
+    }
+
+    body.push_str(
+        "<h2>History</h2><table><tr><th>id</th><th>block</th><th>from</th><th>change</th><th>time</th></tr>"
+    );
+    // page comes from the query string, it can be anything
+    for receipt in chain.address_history(address, page_number.saturating_mul(HISTORY_PAGE), HISTORY_PAGE) {
+        let transaction = &receipt.transaction;
+        let received: u64 = transaction.outputs
+            .iter()
+            .filter(|output| output.recipient_address == address.as_bytes())
+            .map(|output| output.value)
+            .sum();
+        let change = if transaction.sender_address == address.as_bytes() {
+            (received as i128) - (transaction.spent_value() as i128)
+        } else {
+            received as i128
+        };
+        let _ = write!(
+            body,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:+}</td><td>{}</td></tr>",
+            tx_link(&receipt.id),
+            block_link(&receipt.block_hash, &receipt.height.to_string()),
+            address_link(&transaction.sender_address),
+            change,
+            time(receipt.time_stamp)
+        );
+    }
+    body.push_str("</table><p>");
+    if page_number > 0 {
+        let _ = write!(body, "<a href=\"/address/{}?page={}\">&larr; newer</a> ", percent_encode(address), page_number - 1);
+    }
+    if page_number.saturating_add(1).saturating_mul(HISTORY_PAGE) < total {
+        let _ = write!(body, "<a href=\"/address/{}?page={}\">older &rarr;</a>", percent_encode(address), page_number + 1);
+    }
+    body.push_str("</p>");
+    page("Address", &body)
+}
+
+fn mempool_page(chain: &BlockChain) -> Response {
+    let mempool = chain.mempool();
+    let now = Instant::now();
+    let mut body = format!(
+        "<p>{} transactions, {} of {} bytes</p>",
+        mempool.len(),
+        mempool.total_bytes(),
+        mempool.config().max_bytes
+    );
+    body.push_str(
+        "<table><tr><th>id</th><th>from</th><th>value</th><th>fee</th><th>size</th><th>waiting</th><th>depends on</th></tr>"
+    );
+    for entry in mempool.by_fee_rate() {
+        let parents: Vec<String> = entry.parents.iter().map(tx_link).collect();
+        let _ = write!(
+            body,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}s</td><td>{}</td></tr>",
+            tx_link(&entry.id),
+            address_link(&entry.transaction.sender_address),
+            entry.transaction.total_value(),
+            entry.fee(),
+            entry.size,
+            now.duration_since(entry.received).as_secs(),
+            parents.join("<br>")
+        );
+    }
+    body.push_str("</table>");
+    page("Mempool", &body)
+}
+
+fn search(chain: &BlockChain, query: &str) -> Response {
+    // heights, block hashes and transaction ids are tried first, anything else is an address
+    let location = if query.parse::<u64>().is_ok() {
+        format!("/block/{}", query)
+    } else if let Ok(hash) = query.parse::<BlockHash>() {
+        if chain.block_by_hash(&hash).is_some() {
+            format!("/block/{}", hash)
+        } else {
+            format!("/tx/{}", hash)
+        }
+    } else if !query.is_empty() {
+        format!("/address/{}", percent_encode(query))
+    } else {
+        "/".to_string()
+    };
+    Response::redirect(&location)
+}
+
+fn transaction_table(transaction: &Transaction) -> String {
+    let mut table = String::from("<table class=\"tx\">");
+    let _ = write!(table, "<tr><th>id</th><td>{}</td></tr>", tx_link(&transaction.id()));
+    let _ = write!(table, "<tr><th>from</th><td>{}</td></tr>", address_link(&transaction.sender_address));
+    for output in transaction.outputs.iter() {
+        let _ = write!(
+            table,
+            "<tr><th>to</th><td>{} &middot; {}</td></tr>",
+            address_link(&output.recipient_address),
+            output.value
+        );
+    }
+    match &transaction.kind {
+        // the sender row already shows the coinbase
+        TransactionKind::Transfer | TransactionKind::Coinbase { .. } => {}
+        TransactionKind::HtlcCreate(lock) => {
+            let _ = write!(
+                table,
+                "<tr><th>htlc</th><td>locks {} for {} until height {}, hash lock {}</td></tr>",
+                lock.value,
+                address_link(&lock.recipient_address),
+                lock.expiry_height,
+                hex::encode(&lock.hash_lock)
+            );
+        }
+        TransactionKind::HtlcClaim { htlc_id, preimage } => {
+            let _ = write!(
+                table,
+                "<tr><th>htlc claim</th><td>{} with preimage {}</td></tr>",
+                tx_link(htlc_id),
+                hex::encode(preimage)
+            );
+        }
+        TransactionKind::HtlcRefund { htlc_id } => {
+            let _ = write!(table, "<tr><th>htlc refund</th><td>{}</td></tr>", tx_link(htlc_id));
+        }
+        TransactionKind::Stake { public_key, value } => {
+            let _ = write!(table, "<tr><th>stake</th><td>{} for key {}</td></tr>", value, escape(public_key));
+        }
+        TransactionKind::Unstake { value } => {
+            let _ = write!(table, "<tr><th>unstake</th><td>{}</td></tr>", value);
+        }
+        TransactionKind::Slash(evidence) => {
+            let _ = write!(
+                table,
+                "<tr><th>slash</th><td>{} double signed height {}</td></tr>",
+                address_link(&evidence.offender().unwrap_or_default()),
+                evidence.first.height
+            );
+        }
+    }
+    let _ = write!(table, "<tr><th>fee</th><td>{}</td></tr>", transaction.fee);
+    let _ = write!(table, "<tr><th>sequence</th><td>{}</td></tr>", transaction.sequence);
+    match transaction.lock_time {
+        Some(LockTime::Height(height)) => {
+            let _ = write!(table, "<tr><th>lock time</th><td>height {}</td></tr>", height);
+        }
+        Some(LockTime::Timestamp(time_stamp)) => {
+            let _ = write!(table, "<tr><th>lock time</th><td>{}</td></tr>", time(time_stamp));
+        }
+        None => {}
+    }
+    table.push_str("</table>");
+    table
+}
+
+fn block_link(hash: &BlockHash, text: &str) -> String {
+    format!("<a href=\"/block/{}\">{}</a>", hash, escape(text))
+}
+
+fn tx_link(id: &TxId) -> String {
+    format!("<a href=\"/tx/{}\">{}</a>", id, id)
+}
+
+fn address_link(address: &[u8]) -> String {
+    if address == BlockChain::MINING_SENDER.as_bytes() {
+        return "coinbase".to_string();
+    }
+    // chain addresses are the bytes of the base58 string
+    let address = String::from_utf8_lossy(address);
+    format!("<a href=\"/address/{}\">{}</a>", percent_encode(&address), escape(&address))
+}
+
+fn time(time_stamp: u128) -> String {
+    // seconds since the unix epoch, the chain keeps nanoseconds
+    format!("{}", time_stamp / 1_000_000_000)
+}
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn not_found(message: &str) -> Response {
+    page("Not found", &format!("<p>{}</p>", escape(message))).with_status(404)
+}
+
+fn page(title: &str, body: &str) -> Response {
+    Response::html(
+        format!(
+            "<!doctype html><html><head><meta charset=\"utf-8\"><title>{} - explorer</title>\
+            <style>body{{font-family:monospace;margin:2em}}table{{border-collapse:collapse;margin-bottom:1em}}\
+            th,td{{border:1px solid #ccc;padding:2px 8px;text-align:left}}a{{color:#06c}}</style></head>\
+            <body><nav><a href=\"/\">blocks</a> &middot; <a href=\"/mempool\">mempool</a> &middot; \
+            <form style=\"display:inline\" action=\"/search\"><input name=\"q\" size=\"70\" \
+            placeholder=\"height, block hash, transaction id or address\"></form></nav><h1>{}</h1>{}</body></html>",
+            escape(title),
+            escape(title),
+            body
+        )
+    )
+}
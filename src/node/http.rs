@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::io::{ BufRead, BufReader, Read, Write };
+use std::net::TcpStream;
+
+/*
+    just enough http/1.1 for the node: one request per connection, the request
+    line, headers and an optional body with content-length, no chunked encoding
+*/
+
+const MAX_BODY: usize = 1_000_000;
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    // names are lowercased
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn read_from(stream: &mut TcpStream) -> Option<Request> {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let mut parts = line.split_whitespace();
+        let method = parts.next()?.to_string();
+        let target = parts.next()?.to_string();
+
+        let mut headers = HashMap::<String, String>::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':')?;
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+
+        let length: usize = match headers.get("content-length") {
+            Some(length) => length.parse().ok()?,
+            None => 0,
+        };
+        if length > MAX_BODY {
+            return None;
+        }
+        let mut body = vec![0_u8; length];
+        reader.read_exact(&mut body).ok()?;
+
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, parse_query(query)),
+            None => (target.as_str(), HashMap::new()),
+        };
+
+        Some(Request {
+            method,
+            path: percent_decode(path),
+            query,
+            headers,
+            body,
+        })
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(|value| value.as_str())
+    }
+
+    pub fn query_usize(&self, name: &str) -> Option<usize> {
+        self.query.get(name)?.parse().ok()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn html(body: String) -> Response {
+        Response {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            headers: Vec::new(),
+            body: body.into_bytes(),
+        }
+    }
+
+    pub fn json(body: String) -> Response {
+        Response {
+            status: 200,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: body.into_bytes(),
+        }
+    }
+
+    pub fn redirect(location: &str) -> Response {
+        Response {
+            status: 303,
+            content_type: "text/plain; charset=utf-8",
+            headers: vec![("Location", location.to_string())],
+            body: Vec::new(),
+        }
+    }
+
+    pub fn with_status(mut self, status: u16) -> Response {
+        self.status = status;
+        self
+    }
+
+    pub fn not_found() -> Response {
+        Response {
+            status: 404,
+            content_type: "text/plain; charset=utf-8",
+            headers: Vec::new(),
+            body: b"not found".to_vec(),
+        }
+    }
+
+    pub fn bad_request(message: &str) -> Response {
+        Response {
+            status: 400,
+            content_type: "text/plain; charset=utf-8",
+            headers: Vec::new(),
+            body: message.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn write_to(&self, stream: &mut TcpStream) -> std::io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            303 => "See Other",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "",
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len()
+        )?;
+        for (name, value) in self.headers.iter() {
+            write!(stream, "{}: {}\r\n", name, value)?;
+        }
+        stream.write_all(b"\r\n")?;
+        stream.write_all(&self.body)?;
+        stream.flush()
+    }
+}
+
+pub fn get(address: &str, path: &str) -> std::io::Result<String> {
+    // a plain GET against a node, returns the body of any response
+    let mut stream = TcpStream::connect(address)?;
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, address)?;
+    stream.flush()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response.split_once("\r\n\r\n").map_or(String::new(), |(_, body)| body.to_string()))
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect()
+}
+
+pub fn percent_encode(text: &str) -> String {
+    // everything but unreserved characters, for putting text into a url path or query
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::<u8>::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'+' => decoded.push(b' '),
+            b'%' if idx + 2 < bytes.len() => {
+                let hex_digits = std::str::from_utf8(&bytes[idx + 1..idx + 3]).ok();
+                match hex_digits.and_then(|digits| u8::from_str_radix(digits, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        idx += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        idx += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,220 @@
+use crate::blockchain::events::{ ChainEvent, EventFilter };
+use crate::blockchain::stats::MiningStats;
+use crate::blockchain::template::BlockTemplate;
+use crate::blockchain::timestamp;
+use crate::blockchain::BlockChain;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{ Arc, Mutex, MutexGuard };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+/*
+    mines on background threads for as long as it is running. a controller
+    thread watches the chain and the settings, and whenever a block or a
+    transaction arrives or a setting changes it bumps the work generation,
+    which every hashing thread checks between batches of nonces, then hands
+    out a fresh template. the threads split the nonce space between them and
+    submit a solved block like any outside miner would
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinerSettings {
+    pub running: bool,
+    pub threads: usize,
+    pub payout_address: String,
+}
+
+#[derive(Debug)]
+struct MinerControl {
+    settings: MinerSettings,
+    // bumped on every settings change so the controller notices it
+    version: u64,
+    controller_started: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct MinerService {
+    chain: Arc<Mutex<BlockChain>>,
+    control: Arc<Mutex<MinerControl>>,
+    // hashing threads stop as soon as this moves past the generation they started on
+    work: Arc<AtomicU64>,
+    stats: Arc<Mutex<MiningStats>>,
+}
+
+impl MinerService {
+    pub const MAX_THREADS: usize = 64;
+    // nonces a thread tries before checking whether its work is stale
+    const BATCH: u32 = 1_000;
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    pub fn new(chain: Arc<Mutex<BlockChain>>, payout_address: String) -> Self {
+        // blocks the chain mines itself and the ones found here add up in one place
+        let stats = chain.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).mining_stats_handle();
+        MinerService {
+            chain,
+            control: Arc::new(
+                Mutex::new(MinerControl {
+                    settings: MinerSettings {
+                        running: false,
+                        threads: 1,
+                        payout_address,
+                    },
+                    version: 0,
+                    controller_started: false,
+                })
+            ),
+            work: Arc::new(AtomicU64::new(0)),
+            stats,
+        }
+    }
+
+    fn chain(&self) -> MutexGuard<'_, BlockChain> {
+        self.chain.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn control(&self) -> MutexGuard<'_, MinerControl> {
+        self.control.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn stats(&self) -> MiningStats {
+        self.stats.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    fn record(&self, record: impl FnOnce(&mut MiningStats)) {
+        record(&mut self.stats.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+    }
+
+    pub fn settings(&self) -> MinerSettings {
+        self.control().settings.clone()
+    }
+
+    pub fn start(&self) -> bool {
+        {
+            let chain = self.chain();
+            if chain.consensus().pow_target(chain.blocks()).is_none() {
+                println!("only a proof-of-work chain can be mined, not {}", chain.consensus().name());
+                return false;
+            }
+        }
+
+        let spawn_controller = {
+            let mut control = self.control();
+            control.settings.running = true;
+            control.version += 1;
+            !std::mem::replace(&mut control.controller_started, true)
+        };
+        if spawn_controller {
+            let miner = self.clone();
+            thread::spawn(move || miner.control_loop());
+        }
+        true
+    }
+
+    pub fn stop(&self) {
+        let mut control = self.control();
+        control.settings.running = false;
+        control.version += 1;
+    }
+
+    pub fn set_threads(&self, threads: usize) -> bool {
+        if threads == 0 || threads > MinerService::MAX_THREADS {
+            println!("thread count must be between 1 and {}", MinerService::MAX_THREADS);
+            return false;
+        }
+        let mut control = self.control();
+        control.settings.threads = threads;
+        control.version += 1;
+        true
+    }
+
+    pub fn set_payout_address(&self, address: &str) -> bool {
+        if address.is_empty() {
+            println!("payout address is empty");
+            return false;
+        }
+        let mut control = self.control();
+        control.settings.payout_address = address.to_string();
+        control.version += 1;
+        true
+    }
+
+    fn control_loop(&self) {
+        let events = self.chain().subscribe(EventFilter::all());
+        let mut seen_version = None;
+        loop {
+            let mut restart = match events.recv_timeout(MinerService::POLL_INTERVAL) {
+                Ok(event) => MinerService::makes_work_stale(&event),
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return;
+                }
+            };
+            // one restart covers everything that arrived meanwhile
+            for event in events.try_iter() {
+                restart |= MinerService::makes_work_stale(&event);
+            }
+
+            let (settings, version) = {
+                let control = self.control();
+                (control.settings.clone(), control.version)
+            };
+            if seen_version != Some(version) {
+                seen_version = Some(version);
+                restart = true;
+            }
+            if !restart {
+                continue;
+            }
+
+            let generation = self.work.fetch_add(1, Ordering::SeqCst) + 1;
+            if settings.running {
+                let template = self.chain().get_block_template(&settings.payout_address);
+                for idx in 0..settings.threads {
+                    let miner = self.clone();
+                    let template = template.clone();
+                    let threads = settings.threads;
+                    thread::spawn(move || miner.hash(template, idx, threads, generation));
+                }
+            }
+        }
+    }
+
+    fn makes_work_stale(event: &ChainEvent) -> bool {
+        matches!(
+            event,
+            ChainEvent::BlockAppended(_) | ChainEvent::BlockReverted(_) | ChainEvent::TransactionAccepted { .. }
+        )
+    }
+
+    fn hash(&self, template: BlockTemplate, idx: usize, threads: usize, generation: u64) {
+        // thread idx tries every threads-th nonce
+        let mut block = template.block.clone();
+        let first = i32::MIN.wrapping_add(idx as i32);
+        block.header.nonce = first;
+        let started = Instant::now();
+        while self.work.load(Ordering::SeqCst) == generation {
+            let batch_started = Instant::now();
+            for tried in 1..=MinerService::BATCH as u64 {
+                if template.algorithm.meets_difficulty(&block.header) {
+                    self.record(|stats| stats.record_hashes(idx, tried, batch_started.elapsed()));
+                    // the new block restarts every thread, a stale one waits for the next template
+                    if self.chain().submit_block(block) {
+                        self.record(|stats| stats.record_block(started.elapsed()));
+                    }
+                    return;
+                }
+
+                block.header.nonce = match block.header.nonce.checked_add(threads as i32) {
+                    Some(nonce) => nonce,
+                    None => {
+                        // every nonce tried, a later timestamp gives new headers
+                        block.header.time_stamp = timestamp::now().max(block.header.time_stamp + 1);
+                        first
+                    }
+                };
+            }
+            self.record(|stats| stats.record_hashes(idx, MinerService::BATCH as u64, batch_started.elapsed()));
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use crate::blockchain::events::ChainEvent;
+use crate::blockchain::BlockChain;
+use http::{ Request, Response };
+use miner::MinerService;
+use std::net::{ TcpListener, TcpStream };
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{ Arc, Mutex, MutexGuard };
+use std::thread;
+use std::time::Duration;
+
+pub mod http;
+pub mod explorer;
+pub mod websocket;
+pub mod pool;
+pub mod api;
+pub mod miner;
+
+/*
+    the node shares one chain between every connection, each request takes the
+    lock only for as long as it needs to render its response
+*/
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    chain: Arc<Mutex<BlockChain>>,
+    // stopped until started through the api
+    miner: MinerService,
+}
+
+impl Node {
+    const READ_TIMEOUT: Duration = Duration::from_secs(10);
+    const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+    pub fn new(chain: BlockChain) -> Self {
+        let payout_address = chain.miner_address().to_string();
+        let chain = Arc::new(Mutex::new(chain));
+        Node {
+            miner: MinerService::new(chain.clone(), payout_address),
+            chain,
+        }
+    }
+
+    pub fn miner(&self) -> &MinerService {
+        &self.miner
+    }
+
+    pub fn chain(&self) -> MutexGuard<'_, BlockChain> {
+        // a panic in another request must not take the whole node down
+        self.chain.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn serve(&self, address: &str) -> std::io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        println!("node listening on http://{}", listener.local_addr()?);
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let node = self.clone();
+                    thread::spawn(move || node.handle_connection(stream));
+                }
+                Err(e) => println!("connection failed: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_connection(&self, mut stream: TcpStream) {
+        if stream.set_read_timeout(Some(Node::READ_TIMEOUT)).is_err() {
+            return;
+        }
+
+        let response = match Request::read_from(&mut stream) {
+            Some(request) if request.path == "/events" && websocket::is_upgrade(&request) => {
+                if let Err(e) = self.stream_events(stream, &request) {
+                    println!("event stream closed: {}", e);
+                }
+                return;
+            }
+            Some(request) => self.handle(&request),
+            None => Response::bad_request("malformed request"),
+        };
+        if let Err(e) = response.write_to(&mut stream) {
+            println!("failed to send response: {}", e);
+        }
+    }
+
+    fn stream_events(&self, mut stream: TcpStream, request: &Request) -> std::io::Result<()> {
+        // pushes every chain event the filter lets through until the client goes away
+        websocket::accept(&mut stream, request)?;
+        let events = self.chain().subscribe(websocket::filter_from(request));
+        loop {
+            match events.recv_timeout(Node::PING_INTERVAL) {
+                // the client only hears about events it asked for
+                Ok(ChainEvent::Heartbeat) => {}
+                Ok(event) => websocket::send_text(&mut stream, &websocket::event_json(&event).to_string())?,
+                Err(RecvTimeoutError::Timeout) => websocket::send_ping(&mut stream)?,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    pub fn handle(&self, request: &Request) -> Response {
+        if request.path.starts_with("/api/") {
+            return api::route(self, request).unwrap_or_else(Response::not_found);
+        }
+
+        if request.method != "GET" {
+            return Response::bad_request("method not allowed").with_status(405);
+        }
+
+        if request.path == "/events" {
+            return Response::bad_request("/events expects a websocket upgrade");
+        }
+
+        explorer::route(&self.chain(), request).unwrap_or_else(Response::not_found)
+    }
+}
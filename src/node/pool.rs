@@ -0,0 +1,374 @@
+use crate::blockchain::events::{ ChainEvent, EventFilter };
+use crate::blockchain::header::BlockHeader;
+use crate::blockchain::pow::PowAlgorithm;
+use crate::blockchain::Block;
+use crate::node::Node;
+use serde_json::{ json, Value };
+use std::collections::{ BTreeMap, HashMap, HashSet };
+use std::io::{ BufRead, BufReader, Write };
+use std::net::{ TcpListener, TcpStream };
+use std::sync::mpsc::{ channel, Sender, TryRecvError };
+use std::sync::{ Arc, Mutex, MutexGuard };
+use std::thread;
+
+/*
+    a stratum-like mining pool. miners connect over tcp and talk newline
+    delimited json, one object per line:
+
+        miner -> pool  {"id": 1, "method": "mining.authorize", "params": ["name"]}
+        pool -> miner  {"id": 1, "result": true, "error": null}
+        pool -> miner  {"id": null, "method": "mining.notify", "params": [job]}
+        miner -> pool  {"id": 2, "method": "mining.submit", "params": ["job id", nonce]}
+
+    a job is the next block with its coinbase paying the pool, as the hex borsh
+    encoding of its header. every connection gets its own extranonce in the
+    header extra so no two miners search the same headers. a nonce whose hash
+    meets the pool's share difficulty is credited to the miner as a share, one
+    that also meets the block difficulty completes the block for the chain
+*/
+
+#[derive(Debug, Clone)]
+struct Job {
+    block: Block,
+    // (extranonce, nonce) pairs already credited
+    submitted: HashSet<(u64, i32)>,
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    next_job: u64,
+    next_connection: u64,
+    // only jobs on the current tip, cleared whenever the tip moves
+    jobs: HashMap<u64, Job>,
+    current_job: Option<u64>,
+    // connection id to the channel its writer drains
+    connections: HashMap<u64, Sender<Value>>,
+    // connection id to the miner it authorized as
+    miners: HashMap<u64, String>,
+    shares: BTreeMap<String, u64>,
+    blocks_found: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MiningPool {
+    node: Node,
+    payout_address: String,
+    share_difficulty: u32,
+    algorithm: PowAlgorithm,
+    state: Arc<Mutex<PoolState>>,
+}
+
+impl MiningPool {
+    // nonces a miner tries before checking for a newer job
+    const MINER_BATCH: u32 = 1_000;
+
+    pub fn new(node: Node, payout_address: String, share_difficulty: u32) -> Option<Self> {
+        // shares are proof of work, the other engines have nothing to hash
+        let algorithm = {
+            let chain = node.chain();
+            if chain.consensus().pow_target(chain.blocks()).is_none() {
+                println!("a mining pool needs a proof-of-work chain, not {}", chain.consensus().name());
+                return None;
+            }
+            chain.genesis_params().pow_algorithm
+        };
+
+        Some(MiningPool {
+            node,
+            payout_address,
+            share_difficulty,
+            algorithm,
+            state: Arc::new(Mutex::new(PoolState::default())),
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn shares(&self) -> BTreeMap<String, u64> {
+        self.state().shares.clone()
+    }
+
+    pub fn blocks_found(&self) -> BTreeMap<String, u64> {
+        self.state().blocks_found.clone()
+    }
+
+    pub fn serve(&self, address: &str) -> std::io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        println!("mining pool listening on {}", listener.local_addr()?);
+
+        // a new block or transaction makes the current job stale
+        let events = self.node.chain().subscribe(EventFilter::all());
+        let pool = self.clone();
+        thread::spawn(move || {
+            for event in events {
+                match event {
+                    ChainEvent::BlockAppended(_) | ChainEvent::BlockReverted(_) | ChainEvent::TransactionAccepted { .. } => {
+                        pool.new_job();
+                    }
+                    _ => {}
+                }
+            }
+        });
+        self.new_job();
+
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let pool = self.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = pool.handle_miner(stream) {
+                            println!("miner disconnected: {}", e);
+                        }
+                    });
+                }
+                Err(e) => println!("connection failed: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    fn new_job(&self) {
+        let block = self.node.chain().get_block_template(&self.payout_address).block;
+        let mut state = self.state();
+        let id = state.next_job;
+        state.next_job += 1;
+        // a job on another tip can no longer become a block
+        let tip = block.header.previous_hash;
+        state.jobs.retain(|_, job| job.block.header.previous_hash == tip);
+        state.jobs.insert(id, Job { block, submitted: HashSet::new() });
+        state.current_job = Some(id);
+
+        let authorized: Vec<u64> = state.miners.keys().copied().collect();
+        for connection in authorized {
+            if let Some(notify) = self.notify(&state, connection) {
+                if let Some(sender) = state.connections.get(&connection) {
+                    let _ = sender.send(notify);
+                }
+            }
+        }
+    }
+
+    fn share_target(&self, header: &BlockHeader) -> u32 {
+        // retargeting can take the block below the pool's share difficulty, a
+        // block solution then has to count as a share or it would be thrown away
+        self.share_difficulty.min(header.difficulty)
+    }
+
+    fn notify(&self, state: &PoolState, connection: u64) -> Option<Value> {
+        // the current job with this connection's extranonce
+        let id = state.current_job?;
+        let mut header = state.jobs.get(&id)?.block.header.clone();
+        header.extra = connection.to_le_bytes().to_vec();
+        Some(
+            json!({
+                "id": null,
+                "method": "mining.notify",
+                "params": [{
+                    "job_id": id.to_string(),
+                    "header": hex::encode(header.encode()),
+                    "share_difficulty": self.share_target(&header),
+                    "difficulty": header.difficulty,
+                    "algorithm": hex::encode(borsh::to_vec(&self.algorithm).expect("Borsh serialization failed")),
+                }],
+            })
+        )
+    }
+
+    fn handle_miner(&self, stream: TcpStream) -> std::io::Result<()> {
+        let (sender, receiver) = channel::<Value>();
+        let connection = {
+            let mut state = self.state();
+            let connection = state.next_connection;
+            state.next_connection += 1;
+            state.connections.insert(connection, sender.clone());
+            connection
+        };
+
+        // replies and notifications go out through one writer
+        let mut writer = stream.try_clone()?;
+        thread::spawn(move || {
+            for message in receiver {
+                if writeln!(writer, "{}", message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let result = self.read_requests(connection, &stream, &sender);
+        let mut state = self.state();
+        state.connections.remove(&connection);
+        state.miners.remove(&connection);
+        result
+    }
+
+    fn read_requests(&self, connection: u64, stream: &TcpStream, sender: &Sender<Value>) -> std::io::Result<()> {
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            let request: Value = match serde_json::from_str(&line) {
+                Ok(request) => request,
+                Err(_) => {
+                    let _ = sender.send(json!({ "id": null, "result": null, "error": "malformed request" }));
+                    continue;
+                }
+            };
+
+            let id = request["id"].clone();
+            let params = &request["params"];
+            let reply = match request["method"].as_str() {
+                Some("mining.authorize") => self.authorize(connection, params),
+                Some("mining.submit") => self.submit(connection, params),
+                _ => Err("unknown method".to_string()),
+            };
+            let reply = match reply {
+                Ok(result) => json!({ "id": id, "result": result, "error": null }),
+                Err(error) => json!({ "id": id, "result": false, "error": error }),
+            };
+            if sender.send(reply).is_err() {
+                break;
+            }
+
+            // a freshly authorized miner starts on the current job right away
+            if request["method"] == "mining.authorize" {
+                let state = self.state();
+                if let Some(notify) = self.notify(&state, connection) {
+                    let _ = sender.send(notify);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn authorize(&self, connection: u64, params: &Value) -> Result<bool, String> {
+        let name = match params[0].as_str() {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => {
+                return Err("expected a miner name".to_string());
+            }
+        };
+        self.state().miners.insert(connection, name);
+        Ok(true)
+    }
+
+    fn submit(&self, connection: u64, params: &Value) -> Result<bool, String> {
+        let job_id = params[0]
+            .as_str()
+            .and_then(|id| id.parse::<u64>().ok())
+            .ok_or("expected a job id")?;
+        let nonce = params[1]
+            .as_i64()
+            .and_then(|nonce| i32::try_from(nonce).ok())
+            .ok_or("expected a nonce")?;
+
+        let (miner, block) = {
+            let mut state = self.state();
+            let miner = state.miners.get(&connection).cloned().ok_or("not authorized")?;
+            let job = state.jobs.get_mut(&job_id).ok_or("stale job")?;
+            if !job.submitted.insert((connection, nonce)) {
+                return Err("duplicate share".to_string());
+            }
+
+            let mut block = job.block.clone();
+            block.header.extra = connection.to_le_bytes().to_vec();
+            block.header.nonce = nonce;
+            if !self.algorithm.meets_target(&block.header, self.share_target(&block.header)) {
+                return Err("share is above the pool target".to_string());
+            }
+            *state.shares.entry(miner.clone()).or_default() += 1;
+            (miner, block)
+        };
+
+        // most shares stop here, a few meet the block difficulty as well
+        if self.algorithm.meets_difficulty(&block.header) && self.node.chain().submit_block(block) {
+            println!("pool found a block, share of {}", miner);
+            *self.state().blocks_found.entry(miner).or_default() += 1;
+        }
+        Ok(true)
+    }
+}
+
+// a job as the miner sees it
+struct MinerJob {
+    id: String,
+    header: BlockHeader,
+    share_difficulty: u32,
+    algorithm: PowAlgorithm,
+}
+
+impl MinerJob {
+    fn from_notify(job: &Value) -> Option<Self> {
+        let header = hex::decode(job["header"].as_str()?).ok()?;
+        let algorithm = hex::decode(job["algorithm"].as_str()?).ok()?;
+        Some(MinerJob {
+            id: job["job_id"].as_str()?.to_string(),
+            header: borsh::from_slice(&header).ok()?,
+            share_difficulty: u32::try_from(job["share_difficulty"].as_u64()?).ok()?,
+            algorithm: borsh::from_slice(&algorithm).ok()?,
+        })
+    }
+}
+
+pub fn mine_for_pool(address: &str, name: &str) -> std::io::Result<()> {
+    // a simple external miner: takes jobs from the pool and submits every share it finds
+    let stream = TcpStream::connect(address)?;
+    let mut writer = stream.try_clone()?;
+    writeln!(writer, "{}", json!({ "id": 1, "method": "mining.authorize", "params": [name] }))?;
+
+    let (jobs, incoming) = channel::<MinerJob>();
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let message: Value = match line.ok().and_then(|line| serde_json::from_str(&line).ok()) {
+                Some(message) => message,
+                None => {
+                    break;
+                }
+            };
+            if message["method"] == "mining.notify" {
+                if let Some(job) = MinerJob::from_notify(&message["params"][0]) {
+                    if jobs.send(job).is_err() {
+                        break;
+                    }
+                }
+            } else if !message["error"].is_null() {
+                println!("pool refused a share: {}", message["error"]);
+            }
+        }
+    });
+
+    let mut job: Option<MinerJob> = None;
+    let mut next_id = 2u64;
+    loop {
+        // wait for the first job, then look for a newer one between batches of nonces
+        let next = match (&job, incoming.try_recv()) {
+            (_, Ok(next)) => Some(next),
+            (None, Err(TryRecvError::Empty)) => incoming.recv().ok(),
+            (Some(_), Err(TryRecvError::Empty)) => None,
+            (_, Err(TryRecvError::Disconnected)) => {
+                println!("pool closed the connection");
+                return Ok(());
+            }
+        };
+        if next.is_some() {
+            job = next;
+        }
+
+        let job = match job.as_mut() {
+            Some(job) => job,
+            None => {
+                return Ok(());
+            }
+        };
+        for _ in 0..MiningPool::MINER_BATCH {
+            if job.algorithm.meets_target(&job.header, job.share_difficulty) {
+                writeln!(
+                    writer,
+                    "{}",
+                    json!({ "id": next_id, "method": "mining.submit", "params": [job.id, job.header.nonce] })
+                )?;
+                next_id += 1;
+            }
+            job.header.nonce = job.header.nonce.wrapping_add(1);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use crate::blockchain::events::{ BlockEvent, ChainEvent, DropReason, EventFilter };
+use crate::blockchain::transaction::{ Transaction, TransactionKind };
+use crate::node::http::Request;
+use base64::{ engine::general_purpose::STANDARD, Engine };
+use serde_json::{ json, Value };
+use sha1::{ Digest, Sha1 };
+use std::io::Write;
+use std::net::TcpStream;
+
+/*
+    server side of rfc 6455, only what the event stream needs: the opening
+    handshake and unmasked text and ping frames going out. whatever the client
+    sends after the handshake is ignored, a closed connection shows up as a
+    failed write at the latest on the next ping
+*/
+
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_PING: u8 = 0x9;
+
+pub fn is_upgrade(request: &Request) -> bool {
+    request.header("upgrade").is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket")) &&
+        request.header("sec-websocket-key").is_some()
+}
+
+pub fn accept(stream: &mut TcpStream, request: &Request) -> std::io::Result<()> {
+    let key = request.header("sec-websocket-key").unwrap_or_default();
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(HANDSHAKE_GUID.as_bytes());
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        STANDARD.encode(hasher.finalize())
+    )?;
+    stream.flush()
+}
+
+pub fn send_text(stream: &mut TcpStream, text: &str) -> std::io::Result<()> {
+    send_frame(stream, OPCODE_TEXT, text.as_bytes())
+}
+
+pub fn send_ping(stream: &mut TcpStream) -> std::io::Result<()> {
+    send_frame(stream, OPCODE_PING, &[])
+}
+
+fn send_frame(stream: &mut TcpStream, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    // a single final frame, servers never mask
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= (u16::MAX as usize) => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+pub fn filter_from(request: &Request) -> EventFilter {
+    // ?address=<a>,<b> limits the stream to events touching those addresses
+    match request.query.get("address") {
+        Some(addresses) => {
+            let addresses: Vec<&str> = addresses
+                .split(',')
+                .map(|address| address.trim())
+                .filter(|address| !address.is_empty())
+                .collect();
+            EventFilter::addresses(&addresses)
+        }
+        None => EventFilter::all(),
+    }
+}
+
+pub fn event_json(event: &ChainEvent) -> Value {
+    match event {
+        ChainEvent::BlockAppended(block) => block_json("block_appended", block),
+        ChainEvent::BlockReverted(block) => block_json("block_reverted", block),
+        ChainEvent::TransactionAccepted { id, transaction } =>
+            json!({
+                "type": "transaction_accepted",
+                "id": id.to_string(),
+                "transaction": transaction_json(transaction),
+            }),
+        ChainEvent::TransactionDropped { id, transaction, reason } =>
+            json!({
+                "type": "transaction_dropped",
+                "id": id.to_string(),
+                "reason": match reason {
+                    DropReason::Evicted => "evicted",
+                    DropReason::Expired => "expired",
+                    DropReason::Replaced => "replaced",
+                    DropReason::Invalid => "invalid",
+                },
+                "transaction": transaction_json(transaction),
+            }),
+        ChainEvent::CheckpointFinalized(checkpoint) =>
+            json!({
+                "type": "checkpoint_finalized",
+                "height": checkpoint.height,
+                "hash": checkpoint.hash.to_string(),
+            }),
+        ChainEvent::Heartbeat => json!({ "type": "heartbeat" }),
+    }
+}
+
+fn block_json(kind: &str, block: &BlockEvent) -> Value {
+    json!({
+        "type": kind,
+        "height": block.height,
+        "hash": block.hash.to_string(),
+        "transactions": block.transactions.iter().map(transaction_json).collect::<Vec<_>>(),
+    })
+}
+
+pub fn transaction_json(transaction: &Transaction) -> Value {
+    // addresses are shown as the strings they were created from
+    json!({
+        "id": transaction.id().to_string(),
+        "sender": String::from_utf8_lossy(&transaction.sender_address),
+        "outputs": transaction.outputs
+            .iter()
+            .map(|output| json!({
+                "recipient": String::from_utf8_lossy(&output.recipient_address),
+                "value": output.value,
+            }))
+            .collect::<Vec<_>>(),
+        "fee": transaction.fee,
+        "sequence": transaction.sequence,
+        "kind": kind_json(&transaction.kind),
+    })
+}
+
+fn kind_json(kind: &TransactionKind) -> Value {
+    match kind {
+        TransactionKind::Transfer => json!({ "type": "transfer" }),
+        TransactionKind::HtlcCreate(lock) =>
+            json!({
+                "type": "htlc_create",
+                "recipient": String::from_utf8_lossy(&lock.recipient_address),
+                "value": lock.value,
+                "hash_lock": hex::encode(&lock.hash_lock),
+                "expiry_height": lock.expiry_height,
+            }),
+        TransactionKind::HtlcClaim { htlc_id, preimage } =>
+            json!({
+                "type": "htlc_claim",
+                "htlc_id": htlc_id.to_string(),
+                "preimage": hex::encode(preimage),
+            }),
+        TransactionKind::HtlcRefund { htlc_id } =>
+            json!({
+                "type": "htlc_refund",
+                "htlc_id": htlc_id.to_string(),
+            }),
+        TransactionKind::Stake { public_key, value } =>
+            json!({
+                "type": "stake",
+                "public_key": public_key,
+                "value": value,
+            }),
+        TransactionKind::Unstake { value } => json!({ "type": "unstake", "value": value }),
+        TransactionKind::Slash(evidence) =>
+            json!({
+                "type": "slash",
+                "offender": String::from_utf8_lossy(&evidence.offender().unwrap_or_default()),
+                "height": evidence.first.height,
+            }),
+        TransactionKind::Coinbase { height } => json!({ "type": "coinbase", "height": height }),
+    }
+}
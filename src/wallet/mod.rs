@@ -2,7 +2,13 @@ use p256::ecdsa::{ signature::{ Signer, Verifier }, Signature, SigningKey, Verif
 use rand_core::OsRng;
 use sha2::{ Sha256, Digest };
 use ripemd160::{ Ripemd160, Digest as RipDigest };
-use serde::Serialize;
+use serde::{ Deserialize, Serialize };
+use multisig::{ MultisigPolicy, MultisigSignature };
+use crate::blockchain::hash::{ BlockHash, TxId };
+use crate::blockchain::stake::DoubleSignEvidence;
+use crate::blockchain::transaction::LockTime;
+
+pub mod multisig;
 /*
     1. Do sha256 hash on the x,y of public key
     2. Do ripemd160 hash on the result of step 1 and we will get 20 bytes result
@@ -20,64 +26,157 @@ pub struct Wallet {
     address: String,
 }
 
-#[derive(Serialize, Debug, Clone)]
-pub struct Transaction {
-    pub sender: String,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionOutput {
     pub recipient: String,
     pub amount: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum TransactionKind {
+    Transfer,
+    // hash_lock is the hex sha256 of the secret preimage
+    HtlcCreate {
+        recipient: String,
+        amount: u64,
+        hash_lock: String,
+        expiry_height: u64,
+    },
+    HtlcClaim {
+        htlc_id: String,
+        preimage: String,
+    },
+    HtlcRefund {
+        htlc_id: String,
+    },
+    Stake {
+        amount: u64,
+    },
+    Unstake {
+        amount: u64,
+    },
+    // evidence is the hex borsh encoding of a DoubleSignEvidence
+    Slash {
+        evidence: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Transaction {
+    pub sender: String,
+    // a transaction pays one or more recipients under a single signature
+    pub outputs: Vec<TransactionOutput>,
     pub signature: String,
     pub public_key: String,
+    // set when the sender is a multisig address, the signatures field then
+    // carries the co-signers' signatures instead of signature/public_key
+    pub multisig: Option<MultisigPolicy>,
+    pub signatures: Vec<MultisigSignature>,
+    // the pool holds the transaction until the chain reaches the lock time
+    pub lock_time: Option<LockTime>,
+    pub kind: TransactionKind,
+    pub fee: u64,
+    // see BlockChain::next_sequence, reusing a pending sequence with a
+    // higher fee replaces that transaction
+    pub sequence: u64,
+    // hex hash of the genesis block of the chain the transaction is for, it is
+    // signed with everything else so the transaction cannot be replayed on
+    // another chain, say the other side of an htlc swap
+    pub genesis_hash: String,
+}
+
+impl Transaction {
+    pub fn new(kind: TransactionKind, outputs: Vec<TransactionOutput>) -> Transaction {
+        // an unsigned transaction, the sender is filled in when a wallet signs it
+        Transaction {
+            sender: String::new(),
+            outputs,
+            signature: String::new(),
+            public_key: String::new(),
+            multisig: None,
+            signatures: Vec::new(),
+            lock_time: None,
+            kind,
+            fee: 0,
+            sequence: 0,
+            genesis_hash: String::new(),
+        }
+    }
+
+    pub fn new_multisig(policy: MultisigPolicy, outputs: Vec<TransactionOutput>) -> Transaction {
+        let mut transaction = Transaction::new(TransactionKind::Transfer, outputs);
+        transaction.sender = policy.address();
+        transaction.multisig = Some(policy);
+        transaction
+    }
+
+    pub fn with_fee(mut self, fee: u64) -> Transaction {
+        self.fee = fee;
+        self
+    }
+
+    pub fn with_sequence(mut self, sequence: u64) -> Transaction {
+        self.sequence = sequence;
+        self
+    }
+
+    pub fn with_lock_time(mut self, lock_time: LockTime) -> Transaction {
+        self.lock_time = Some(lock_time);
+        self
+    }
+
+    pub fn for_chain(mut self, genesis_hash: &BlockHash) -> Transaction {
+        self.genesis_hash = genesis_hash.to_string();
+        self
+    }
+
+    pub fn signing_payload(&self) -> Vec<u8> {
+        // every signer signs the transaction with all signatures stripped
+        let mut transaction_clone = self.clone();
+        transaction_clone.signature = String::new();
+        transaction_clone.signatures = Vec::new();
+        serde_json::to_string(&transaction_clone).unwrap().into_bytes()
+    }
+
+    pub fn total_amount(&self) -> Option<u64> {
+        // None if the sum of the outputs and the fee overflows
+        self.outputs.iter().try_fold(self.fee, |total, output| total.checked_add(output.amount))
+    }
+}
+
+impl Default for Wallet {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Wallet {
+    const ADDRESS_VERSION: u8 = 0x00;
+
     pub fn new() -> Self {
         // Generate a random signing (private) key
         let signing_key = SigningKey::random(&mut OsRng);
 
         // Derive the corresponding verifying (public) key
         // `.verifying_key()` returns a reference, so we clone it to get an owned value
-        let verifying_key = signing_key.verifying_key().clone();
-
-        let mut address = String::new();
-        let mut gen_address = || {
-            let key_points = verifying_key.to_encoded_point(false);
-
-            if let (Some(x), Some(y)) = (key_points.x(), key_points.y()) {
-                let mut pub_key_bytes = Vec::with_capacity(x.len() + y.len());
-                pub_key_bytes.extend_from_slice(x);
-                pub_key_bytes.extend_from_slice(y);
-
-                // sha256 on public key
-                let hash = Sha256::digest(&pub_key_bytes);
-                //ripemd160 hash on sha256 hash
-                let mut hasher = Ripemd160::new();
-                hasher.update(&hash);
-                let mut hash_result = hasher.finalize().to_vec();
-                //add byte version in front of ripemd160 hash (0x00 for mainnet)
-                hash_result.insert(0, 0x00);
-                //do sha256 hash on the result
-                let hash2 = Sha256::digest(&hash_result);
-                //take the first 4 bytes
-
-                //do shash256 on the previous sha256 hash
-                let hash3 = Sha256::digest(&hash2);
-                let checksum = &hash3[0..4];
-                //add checksum adn the end of extended ripemd160 hash
-                let full_hash = [hash_result, checksum.to_vec()].concat();
-                //base58 encode the result
-                address = bs58::encode(full_hash).into_string();
-            } else {
-                address = String::new();
-            }
-        };
-
-        gen_address();
+        let verifying_key = *signing_key.verifying_key();
 
-        Self {
+        let mut wallet = Self {
             signing_key,
             verifying_key,
-            address,
+            address: String::new(),
+        };
+        wallet.address = Wallet::address_from_public_key(&wallet.public_key_str()).unwrap_or_default();
+        wallet
+    }
+
+    pub fn address_from_public_key(public_key: &str) -> Option<String> {
+        // the public key string is the hex encoded x,y of the key
+        let pub_key_bytes = hex::decode(public_key).ok()?;
+        if pub_key_bytes.len() != 64 {
+            return None;
         }
+        Some(encode_address(Wallet::ADDRESS_VERSION, &pub_key_bytes))
     }
 
     pub fn private_key_str(&self) -> String {
@@ -92,8 +191,7 @@ impl Wallet {
         // If x and y coordinates are present, encode them
         if let (Some(x), Some(y)) = (key_points.x(), key_points.y()) {
             // Convert both x and y to hex and concatenate
-            let pub_str = hex::encode(x) + hex::encode(y).as_str();
-            pub_str
+            hex::encode(x) + hex::encode(y).as_str()
         } else {
             // Return an empty string
             String::new()
@@ -104,55 +202,186 @@ impl Wallet {
         self.address.clone()
     }
 
-    pub fn sign_transaction(&self, receiver: &String, amount: u64) -> Transaction {
-        let mut transaction = Transaction {
-            sender: self.address.clone(),
-            recipient: receiver.clone(),
+    // the sign_* helpers sign for the chain whose genesis block hashes to genesis_hash
+    pub fn sign_transaction(&self, genesis_hash: &BlockHash, receiver: &str, amount: u64) -> Transaction {
+        self.sign_batch_transaction(
+            genesis_hash,
+            vec![TransactionOutput {
+                recipient: receiver.to_string(),
+                amount,
+            }]
+        )
+    }
+
+    pub fn sign_batch_transaction(&self, genesis_hash: &BlockHash, outputs: Vec<TransactionOutput>) -> Transaction {
+        self.sign_locked_transaction(genesis_hash, outputs, None)
+    }
+
+    pub fn sign_locked_transaction(
+        &self,
+        genesis_hash: &BlockHash,
+        outputs: Vec<TransactionOutput>,
+        lock_time: Option<LockTime>
+    ) -> Transaction {
+        let mut transaction = Transaction::new(TransactionKind::Transfer, outputs);
+        transaction.lock_time = lock_time;
+        self.sign(transaction.for_chain(genesis_hash))
+    }
+
+    pub fn sign_htlc_create(
+        &self,
+        genesis_hash: &BlockHash,
+        recipient: &str,
+        amount: u64,
+        hash_lock: &[u8],
+        expiry_height: u64
+    ) -> Transaction {
+        let kind = TransactionKind::HtlcCreate {
+            recipient: recipient.to_string(),
             amount,
-            signature: String::new(),
-            public_key: self.public_key_str(),
+            hash_lock: hex::encode(hash_lock),
+            expiry_height,
+        };
+        self.sign(Transaction::new(kind, Vec::new()).for_chain(genesis_hash))
+    }
+
+    pub fn sign_htlc_claim(&self, genesis_hash: &BlockHash, htlc_id: &TxId, preimage: &[u8]) -> Transaction {
+        let kind = TransactionKind::HtlcClaim {
+            htlc_id: htlc_id.to_string(),
+            preimage: hex::encode(preimage),
+        };
+        self.sign(Transaction::new(kind, Vec::new()).for_chain(genesis_hash))
+    }
+
+    pub fn sign_htlc_refund(&self, genesis_hash: &BlockHash, htlc_id: &TxId) -> Transaction {
+        let kind = TransactionKind::HtlcRefund {
+            htlc_id: htlc_id.to_string(),
+        };
+        self.sign(Transaction::new(kind, Vec::new()).for_chain(genesis_hash))
+    }
+
+    pub fn sign_stake(&self, genesis_hash: &BlockHash, amount: u64) -> Transaction {
+        self.sign(Transaction::new(TransactionKind::Stake { amount }, Vec::new()).for_chain(genesis_hash))
+    }
+
+    pub fn sign_unstake(&self, genesis_hash: &BlockHash, amount: u64) -> Transaction {
+        self.sign(Transaction::new(TransactionKind::Unstake { amount }, Vec::new()).for_chain(genesis_hash))
+    }
+
+    pub fn sign_slash(&self, genesis_hash: &BlockHash, evidence: &DoubleSignEvidence) -> Transaction {
+        let kind = TransactionKind::Slash {
+            evidence: hex::encode(borsh::to_vec(evidence).expect("Borsh serialization failed")),
         };
+        self.sign(Transaction::new(kind, Vec::new()).for_chain(genesis_hash))
+    }
 
-        let serialized_str = serde_json::to_string(&transaction).unwrap();
-        let serialized = serialized_str.as_bytes();
-        let sig: Signature = self.signing_key.sign(serialized);
-        transaction.signature = hex::encode(sig.to_bytes());
+    pub fn sign(&self, transaction: Transaction) -> Transaction {
+        // sign a transaction built with Transaction::new as coming from this wallet,
+        // the chain it is for has to be set already, see Transaction::for_chain
+        let mut transaction = transaction;
+        transaction.sender = self.address.clone();
+        transaction.public_key = self.public_key_str();
+        transaction.multisig = None;
+        transaction.signatures = Vec::new();
+        transaction.signature = self.sign_message(&transaction.signing_payload());
         transaction
     }
 
+    pub fn cosign_transaction(&self, transaction: &mut Transaction) {
+        // add this wallet's signature to a multisig transaction
+        let signature = self.sign_message(&transaction.signing_payload());
+        transaction.signatures.push(MultisigSignature {
+            public_key: self.public_key_str(),
+            signature,
+        });
+    }
+
+    pub fn sign_message(&self, payload: &[u8]) -> String {
+        // hex signature over arbitrary bytes, checked with verify_signature
+        let sig: Signature = self.signing_key.sign(payload);
+        hex::encode(sig.to_bytes())
+    }
+
     pub fn verify_transaction(transaction: &Transaction) -> bool {
-        let signature_str = transaction.signature.clone();
-        let signature_bin = hex::decode(signature_str).unwrap();
-        let mut transaction_clone = transaction.clone();
-        transaction_clone.signature = String::new();
+        let serialized = transaction.signing_payload();
 
-        let serialized_str = serde_json::to_string(&transaction_clone).unwrap();
-        let serialized = serialized_str.as_bytes();
+        if let Some(policy) = &transaction.multisig {
+            return policy.verify(&transaction.sender, &transaction.signatures, &serialized);
+        }
 
-        //convert the signature from string to instance of Signature struct
-        //need to make sure the binary data is 64 bytes long
-        let sig_array: [u8; 64] = signature_bin.try_into().unwrap();
+        // the sender must be the address of the key that signed the transaction
+        if Wallet::address_from_public_key(&transaction.public_key).as_ref() != Some(&transaction.sender) {
+            println!("sender address does not match the public key");
+            return false;
+        }
 
-        //param for from_bytes is GenericArray
-        let signature = match Signature::from_bytes(&sig_array.into()) {
-            Ok(sig) => sig,
-            Err(e) => {
-                println!("error: {:?}", e);
-                return false;
-            }
-        };
+        verify_signature(&transaction.public_key, &transaction.signature, &serialized)
+    }
+}
+
+pub(crate) fn encode_address(version: u8, payload: &[u8]) -> String {
+    // sha256 on payload
+    let hash = Sha256::digest(payload);
+    //ripemd160 hash on sha256 hash
+    let mut hasher = Ripemd160::new();
+    hasher.update(hash);
+    let mut hash_result = hasher.finalize().to_vec();
+    //add byte version in front of ripemd160 hash (0x00 for mainnet)
+    hash_result.insert(0, version);
+    //do sha256 hash on the result
+    let hash2 = Sha256::digest(&hash_result);
+    //do shash256 on the previous sha256 hash
+    let hash3 = Sha256::digest(hash2);
+    //take the first 4 bytes
+    let checksum = &hash3[0..4];
+    //add checksum adn the end of extended ripemd160 hash
+    let full_hash = [hash_result, checksum.to_vec()].concat();
+    //base58 encode the result
+    bs58::encode(full_hash).into_string()
+}
+
+pub(crate) fn verify_signature(public_key: &str, signature: &str, payload: &[u8]) -> bool {
+    let signature_bin = match hex::decode(signature) {
+        Ok(bin) => bin,
+        Err(_) => {
+            return false;
+        }
+    };
 
-        let public_key_str = transaction_clone.public_key.clone();
-        //conver the binary data into VerifyingKey
-        let mut public_key_bin = hex::decode(public_key_str).unwrap();
-        /*
-        if we want to convert binary data into VerifyingKey, we need to make sure the
-        binary data is in sec1 format: [0x04 || x coordinate || y coordinate]
-        public_key_bin => [x || y]
-        insert (0x04)
-        */
-        public_key_bin.insert(0, 0x04);
-        let public_key = VerifyingKey::from_sec1_bytes(&public_key_bin).unwrap();
-        public_key.verify(serialized, &signature).is_ok()
+    //convert the signature from string to instance of Signature struct
+    //need to make sure the binary data is 64 bytes long
+    let sig_array: [u8; 64] = match signature_bin.try_into() {
+        Ok(array) => array,
+        Err(_) => {
+            return false;
+        }
+    };
+
+    //param for from_bytes is GenericArray
+    let signature = match Signature::from_bytes(&sig_array.into()) {
+        Ok(sig) => sig,
+        Err(e) => {
+            println!("error: {:?}", e);
+            return false;
+        }
+    };
+
+    //conver the binary data into VerifyingKey
+    let mut public_key_bin = match hex::decode(public_key) {
+        Ok(bin) => bin,
+        Err(_) => {
+            return false;
+        }
+    };
+    /*
+    if we want to convert binary data into VerifyingKey, we need to make sure the
+    binary data is in sec1 format: [0x04 || x coordinate || y coordinate]
+    public_key_bin => [x || y]
+    insert (0x04)
+    */
+    public_key_bin.insert(0, 0x04);
+    match VerifyingKey::from_sec1_bytes(&public_key_bin) {
+        Ok(public_key) => public_key.verify(payload, &signature).is_ok(),
+        Err(_) => false,
     }
 }
@@ -0,0 +1,140 @@
+use crate::wallet::{ encode_address, verify_signature, Wallet };
+use serde::{ Deserialize, Serialize };
+use std::collections::HashSet;
+
+/*
+    a multisig address is derived the same way as a single key address, but the
+    payload is the sorted list of public keys followed by the threshold, and the
+    version byte is 0x05 so the two kinds of address cannot be confused
+*/
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MultisigPolicy {
+    // lowercase hex encoded x,y public keys, kept sorted and without duplicates
+    pub public_keys: Vec<String>,
+    pub threshold: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MultisigSignature {
+    pub public_key: String,
+    pub signature: String,
+}
+
+impl MultisigPolicy {
+    const ADDRESS_VERSION: u8 = 0x05;
+
+    pub fn new(public_keys: Vec<String>, threshold: usize) -> Option<MultisigPolicy> {
+        // the same key in another case is the same signer
+        let mut public_keys: Vec<String> = public_keys
+            .iter()
+            .map(|key| key.to_lowercase())
+            .collect();
+        if public_keys.iter().any(|key| Wallet::address_from_public_key(key).is_none()) {
+            return None;
+        }
+        public_keys.sort();
+        public_keys.dedup();
+
+        // M-of-N requires 1 <= M <= N
+        if threshold == 0 || threshold > public_keys.len() {
+            return None;
+        }
+
+        Some(MultisigPolicy {
+            public_keys,
+            threshold,
+        })
+    }
+
+    pub fn address(&self) -> String {
+        let mut payload = Vec::<u8>::new();
+        for key in self.public_keys.iter() {
+            payload.extend(hex::decode(key).unwrap_or_default());
+        }
+        payload.extend((self.threshold as u64).to_be_bytes());
+        encode_address(MultisigPolicy::ADDRESS_VERSION, &payload)
+    }
+
+    pub fn verify(&self, sender: &str, signatures: &[MultisigSignature], payload: &[u8]) -> bool {
+        // the policy has to be the one the sender address was derived from
+        if MultisigPolicy::new(self.public_keys.clone(), self.threshold).as_ref() != Some(self) {
+            println!("malformed multisig policy");
+            return false;
+        }
+
+        if self.address() != sender {
+            println!("sender address does not match the multisig policy");
+            return false;
+        }
+
+        // a key only counts once no matter how many times it signed
+        let mut signers = HashSet::<String>::new();
+        for sig in signatures.iter() {
+            let public_key = sig.public_key.to_lowercase();
+            if !self.public_keys.contains(&public_key) {
+                continue;
+            }
+
+            if verify_signature(&sig.public_key, &sig.signature, payload) {
+                signers.insert(public_key);
+            }
+        }
+
+        if signers.len() < self.threshold {
+            println!("multisig transaction has {} of {} required signatures", signers.len(), self.threshold);
+            return false;
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(wallet: &Wallet, payload: &[u8]) -> MultisigSignature {
+        MultisigSignature {
+            public_key: wallet.public_key_str(),
+            signature: wallet.sign_message(payload),
+        }
+    }
+
+    #[test]
+    fn threshold_of_signers_is_accepted() {
+        let wallets = [Wallet::new(), Wallet::new(), Wallet::new()];
+        let keys = wallets.iter().map(|wallet| wallet.public_key_str()).collect();
+        let policy = MultisigPolicy::new(keys, 2).unwrap();
+        let payload = b"payload";
+
+        let signatures = [signature(&wallets[0], payload), signature(&wallets[2], payload)];
+        assert!(policy.verify(&policy.address(), &signatures, payload));
+        assert!(!policy.verify(&policy.address(), &signatures[..1], payload));
+        // signatures over something else do not count
+        assert!(!policy.verify(&policy.address(), &signatures, b"other payload"));
+    }
+
+    #[test]
+    fn duplicate_signers_count_once() {
+        let (first, second) = (Wallet::new(), Wallet::new());
+        let policy = MultisigPolicy::new(vec![first.public_key_str(), second.public_key_str()], 2).unwrap();
+        let payload = b"payload";
+
+        let mut shouting = signature(&first, payload);
+        shouting.public_key = shouting.public_key.to_uppercase();
+        let signatures = [signature(&first, payload), signature(&first, payload), shouting];
+        assert!(!policy.verify(&policy.address(), &signatures, payload));
+    }
+
+    #[test]
+    fn policy_keys_are_normalised_and_validated() {
+        let wallet = Wallet::new();
+        let key = wallet.public_key_str();
+
+        // one key in two cases is one signer, so it cannot make up a 2-of-2
+        assert_eq!(MultisigPolicy::new(vec![key.clone(), key.to_uppercase()], 2), None);
+        assert_eq!(MultisigPolicy::new(vec![key.to_uppercase()], 1), MultisigPolicy::new(vec![key.clone()], 1));
+        assert_eq!(MultisigPolicy::new(vec![key, "not a key".to_string()], 1), None);
+    }
+}